@@ -24,6 +24,11 @@ function DefaultGameConfig:InjectConfig(config)
     self.config = config
 end
 
+--- report a failed business rule of this config, `row` is the index into `self.config` data or nil for the whole sheet
+function DefaultGameConfig:ReportError(row, msg)
+    RustUtil.ReportError(self.name, row, msg)
+end
+
 function DefaultGameConfig:OnAllConfigInjectComplete()
 
 end
\ No newline at end of file
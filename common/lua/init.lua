@@ -5,4 +5,5 @@
 ---
 require("lua/ext/class")
 require("lua/game_config")
-require("lua/init_config")
\ No newline at end of file
+require("lua/init_config")
+require("lua/lang")
\ No newline at end of file
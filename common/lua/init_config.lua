@@ -4,61 +4,105 @@
 --- DateTime: 2023/3/12 15:45
 ---
 require("lua/config_load_order")
-local rawGameConfigs = {}
 
-local generatedFiles = RustUtil.ListFiles("lua/generated_excel", true, "lua")
-for _, file in ipairs(generatedFiles) do
-    local configFile = RustUtil.StripSuffix(file, ".lua")
-    local excelConfig = require(configFile)
-    rawGameConfigs[excelConfig.name] = excelConfig
-end
+local generatedExcelDir = "lua/generated_excel"
+local excelDir = "lua/excel"
 
-GameConfigs = {}
+--- @param generatedDir string
+local function LoadGameConfigs(generatedDir)
+    local rawGameConfigs = {}
 
-local excelFiles = RustUtil.ListFiles("lua/excel", true, "lua")
+    local generatedFiles = RustUtil.ListFiles(generatedDir, true, "lua")
+    for _, file in ipairs(generatedFiles) do
+        local configFile = RustUtil.StripSuffix(file, ".lua")
+        local excelConfig = require(configFile)
+        rawGameConfigs[excelConfig.name] = excelConfig
+    end
 
-for _, file in ipairs(excelFiles) do
-    local configFile = RustUtil.StripSuffix(file, ".lua")
-    local excelConfig = require(configFile).New()
-    local name = excelConfig:GetName()
-    local config = rawGameConfigs[name]
-    excelConfig:InjectConfig(config)
-    GameConfigs[name] = excelConfig
-end
+    GameConfigs = {}
 
-for name, rawConfig in pairs(rawGameConfigs) do
-    if not GameConfigs[name] then
-        local defaultConfig = DefaultGameConfig.New()
-        defaultConfig.name = name
-        defaultConfig:InjectConfig(rawConfig)
-        GameConfigs[name] = defaultConfig
+    local excelFiles = RustUtil.ListFiles(excelDir, true, "lua")
+
+    for _, file in ipairs(excelFiles) do
+        local configFile = RustUtil.StripSuffix(file, ".lua")
+        local excelConfig = require(configFile).New()
+        local name = excelConfig:GetName()
+        local config = rawGameConfigs[name]
+        excelConfig:InjectConfig(config)
+        GameConfigs[name] = excelConfig
     end
-end
 
-local completeLoaded = {}
+    for name, rawConfig in pairs(rawGameConfigs) do
+        if not GameConfigs[name] then
+            local defaultConfig = DefaultGameConfig.New()
+            defaultConfig.name = name
+            defaultConfig:InjectConfig(rawConfig)
+            GameConfigs[name] = defaultConfig
+        end
+    end
+
+    local completeLoaded = {}
+    local errorCount = RustUtil.ErrorCount()
 
-for name, gameConfig in pairs(GameConfigs) do
-    local firstOnComplete = ConfigLoadOrder:GetCompleteFirst()
-    if firstOnComplete[name] then
-        gameConfig:OnAllConfigInjectComplete()
+    --- a failing hook is reported and the remaining hooks still run, so one check reports every broken sheet
+    local function OnComplete(name, gameConfig)
+        local ok, err = pcall(gameConfig.OnAllConfigInjectComplete, gameConfig)
+        if not ok then
+            RustUtil.ReportError(name, nil, tostring(err))
+        end
         completeLoaded[name] = {}
     end
-end
 
-for name, gameConfig in pairs(GameConfigs) do
-    local lastOnComplete = ConfigLoadOrder:GetCompleteLast()
-    if lastOnComplete[name] then
-        gameConfig:OnAllConfigInjectComplete()
-        completeLoaded[name] = {}
+    for name, gameConfig in pairs(GameConfigs) do
+        local firstOnComplete = ConfigLoadOrder:GetCompleteFirst()
+        if firstOnComplete[name] then
+            OnComplete(name, gameConfig)
+        end
+    end
+
+    for name, gameConfig in pairs(GameConfigs) do
+        local lastOnComplete = ConfigLoadOrder:GetCompleteLast()
+        if lastOnComplete[name] then
+            OnComplete(name, gameConfig)
+        end
+    end
+
+    for name, gameConfig in pairs(GameConfigs) do
+        if completeLoaded[name] == nil then
+            OnComplete(name, gameConfig)
+        end
+    end
+
+    local reported = RustUtil.ErrorCount() - errorCount
+    if reported > 0 then
+        error(string.format("%d config errors reported", reported))
     end
 end
 
-for name, gameConfig in pairs(GameConfigs) do
-    if completeLoaded[name] == nil then
-        gameConfig:OnAllConfigInjectComplete()
-        completeLoaded[name] = {}
+--- load configs into a fresh GameConfigs, the previous GameConfigs and modules are restored if any hook fails,
+--- called by `ConfigReloader::reload` after the rust tables of the same export are loaded and checked
+--- @param generatedDir string|nil generated lua of the new export, lua/generated_excel if nil
+--- @return boolean, string
+function ReloadGameConfigs(generatedDir)
+    generatedDir = generatedDir or generatedExcelDir
+    local previousGameConfigs = GameConfigs
+    local previousLoaded = {}
+    for _, dir in ipairs({ generatedDir, excelDir }) do
+        for _, file in ipairs(RustUtil.ListFiles(dir, true, "lua")) do
+            local configFile = RustUtil.StripSuffix(file, ".lua")
+            previousLoaded[configFile] = package.loaded[configFile]
+            package.loaded[configFile] = nil
+        end
+    end
+    local ok, err = pcall(LoadGameConfigs, generatedDir)
+    if not ok then
+        GameConfigs = previousGameConfigs
+        for configFile, loaded in pairs(previousLoaded) do
+            package.loaded[configFile] = loaded
+        end
+        return false, tostring(err)
     end
+    return true, nil
 end
 
-rawGameConfigs = nil
-completeLoaded = nil
\ No newline at end of file
+LoadGameConfigs(generatedExcelDir)
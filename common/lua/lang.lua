@@ -0,0 +1,38 @@
+---
+--- lang texts of the generated `lang_<Language>` packs, see `LangPacks` for the rust side
+---
+Lang = {}
+
+local packs = {}
+local packsOf = nil
+
+--- packs are indexed again after the configs are reloaded
+local function GetPacks()
+    if packsOf ~= GameConfigs then
+        packs = {}
+        for _, gameConfig in pairs(GameConfigs) do
+            local config = gameConfig:GetConfig()
+            if type(config.language) == "number" and type(config.source) == "boolean" then
+                packs[config.language] = config
+                if config.source then
+                    packs.source = config
+                end
+            end
+        end
+        packsOf = GameConfigs
+    end
+    return packs
+end
+
+--- text of a lang key in `language`, a value of the proto `Language` enum such as `LoginRequest.language`,
+--- falling back to the source language and then to the key itself
+--- @return string
+function Lang.Get(language, key)
+    local allPacks = GetPacks()
+    local pack = allPacks[language]
+    local text = pack and pack[key]
+    if text == nil and allPacks.source then
+        text = allPacks.source[key]
+    end
+    return text or key
+end
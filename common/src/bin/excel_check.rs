@@ -1,17 +1,32 @@
+use anyhow::{anyhow, Context};
 use clap::Parser;
+use tracing::info;
 
 use common::excel::checker::{Checker, LuaChecker};
+use common::init_logger;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 struct CheckArg {
     #[clap(long, short, default_value = "common/lua/rust_entry.lua")]
     path: String,
+    /// write all errors reported by the lua scripts to this file as json
+    #[clap(long)]
+    report_json: Option<String>,
 }
 
 fn main() -> anyhow::Result<()> {
     let arg = CheckArg::parse();
+    init_logger(tracing::Level::INFO).context("failed to init logger")?;
     let checker = LuaChecker;
-    checker.check(arg.path)?;
+    let report = checker.check(arg.path)?;
+    if let Some(report_json) = &arg.report_json {
+        report.write_json(report_json)?;
+    }
+    if !report.is_empty() {
+        report.log();
+        return Err(anyhow!(format!("excel check failed with {} errors", report.len())));
+    }
+    info!("excel check passed");
     Ok(())
 }
\ No newline at end of file
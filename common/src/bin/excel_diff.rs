@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+use common::excel::config_loader::read_game_configs;
+use common::excel::diff::ConfigDiff;
+use common::excel::excel_define::{ExportSide, GameConfigs};
+use common::excel::export::{ExportOptions, prepare_configs};
+use common::excel::report::ErrorReport;
+use common::excel::time_define::parse_timezone;
+use common::excel::workbook::{InputFormat, read_workbook};
+use common::init_logger;
+
+/// compare two exports, each one is a config.bytes or an excel input dir read and resolved the same way as excel_tool,
+/// exits with an error if they differ
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct DiffArgs {
+    #[clap(long)]
+    old: String,
+    #[clap(long)]
+    new: String,
+    /// also write the diff as json, for review tools
+    #[clap(long)]
+    json: Option<String>,
+    /// compare the client bundle of input dirs, the server bundle if not given, must match the side of a config.bytes
+    #[clap(long)]
+    client: bool,
+    /// the same as excel_tool --proto-enum-path, for input dirs
+    #[clap(long)]
+    proto_enum_path: Option<String>,
+    /// the same as excel_tool --source-language, for input dirs
+    #[clap(long)]
+    source_language: Option<String>,
+    /// the same as excel_tool --timezone, for input dirs
+    #[clap(long, default_value = "UTC")]
+    timezone: String,
+    #[clap(long, value_parser = clap::value_parser ! (tracing::Level), default_value = "warn")]
+    log_level: tracing::Level,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = DiffArgs::parse();
+    init_logger(args.log_level).context("failed to init logger")?;
+    let old = read_configs(&args.old, &args)?;
+    let new = read_configs(&args.new, &args)?;
+    let diff = ConfigDiff::diff(&old, &new);
+    if let Some(json) = &args.json {
+        std::fs::write(json, serde_json::to_string_pretty(&diff)?).context(format!("failed to write: {}", json))?;
+    }
+    if diff.is_empty() {
+        info!("no changes");
+        return Ok(());
+    }
+    print!("{}", diff);
+    Err(anyhow!(format!("{} sheets added, {} sheets removed, {} sheets changed", diff.added_sheets.len(), diff.removed_sheets.len(), diff.changed_sheets.len())))
+}
+
+/// sheets that fail to read would show up as removed, so any error fails the diff
+fn read_configs(path: &str, args: &DiffArgs) -> anyhow::Result<GameConfigs> {
+    if !Path::new(path).is_dir() {
+        return read_game_configs(path);
+    }
+    let mut report = ErrorReport::default();
+    let mut game_configs = GameConfigs::default();
+    for entry in WalkDir::new(path) {
+        let path: PathBuf = entry?.into_path();
+        if !path.is_file() {
+            continue;
+        }
+        if InputFormat::detect(&path).is_some() {
+            let display_path = path.display().to_string();
+            let configs = read_workbook(path, &mut report).context(format!("failed to read: {}", display_path))?;
+            game_configs.data.extend(configs);
+        } else {
+            warn!("ignore files that are not of an input format: {}", path.display());
+        }
+    }
+    let side = if args.client { ExportSide::Client } else { ExportSide::Server };
+    let options = ExportOptions::builder()
+        .proto_enum_path(args.proto_enum_path.clone())
+        .source_language(args.source_language.clone())
+        .timezone(parse_timezone(&args.timezone)?)
+        .sides(vec![side])
+        .build();
+    prepare_configs(&mut game_configs, &options, &mut report)?;
+    if !report.is_empty() {
+        report.log();
+        return Err(anyhow!(format!("failed to read {} with {} errors", path, report.len())));
+    }
+    Ok(game_configs.for_side(side))
+}
@@ -1,22 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::env;
-use std::fs::File;
 use std::io::Write;
 use std::ops::Not;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context};
-use calamine::{DataType, open_workbook, Reader, Xlsx};
 use clap::Parser;
-use lz4::EncoderBuilder;
-use tracing::{error, info, warn};
+use convert_case::Case;
+use rayon::prelude::*;
+use tracing::{info, warn};
 use walkdir::WalkDir;
 
-use common::excel::checker::{CellChecker, Checker};
+use common::excel::bundle::write_bundle;
 use common::excel::convert::ToLua;
-use common::excel::excel_define::{CellType, GameConfig, GameConfigs, KeyType};
+use common::excel::enum_define::{ENUM_SHEET, EnumDefines};
+use common::excel::export::{ExportDefines, ExportOptions, prepare_configs};
+use common::excel::excel_define::{CellType, ExportSide, GameConfig, GameConfigs};
+use common::excel::lang_define::{LANG_DIR, LangTexts};
+use common::excel::lua_emitter::{lua_comment, LuaOptions};
+use common::excel::manifest::{content_hash, ExportManifest, MANIFEST_FILE, OUTPUT_FORMAT_VERSION};
+use common::excel::mapped_table::{MappedTables, write_tables};
+use common::excel::proto_writer::{proto_ident, PROTO_TYPES_FILE, ProtoWriter};
+use common::excel::revision::git_commit_id;
+use common::excel::report::{ErrorReport, ExcelError};
+use common::excel::rust_writer::{rust_ident, RustWriter};
+use common::excel::time_define::parse_timezone;
+use common::excel::workbook::{InputFormat, read_workbook};
 use common::init_logger;
 
 #[derive(Parser, Debug)]
@@ -24,18 +34,73 @@ use common::init_logger;
 struct ExcelArgs {
     #[clap(long, short)]
     input_path: String,
+    /// worker threads reading and checking the workbooks, the number of cpus if 0
+    #[clap(long, default_value_t = 0)]
+    jobs: usize,
+    /// input formats to read separated by `,`, e.g. xlsx,csv, every supported format if not given
+    #[clap(long, value_delimiter = ',')]
+    format: Vec<InputFormat>,
     #[clap(long, short)]
     bytes: bool,
+    /// config.tables for servers to memory map, see `MappedTables`
+    #[clap(long)]
+    tables: bool,
     #[clap(long, short)]
     lua: bool,
+    /// share the tables repeated in a sheet between its rows in the lua output, the rows must not modify them
+    #[clap(long)]
+    lua_compact: bool,
+    #[clap(long, short)]
+    rust: bool,
+    #[clap(long, short)]
+    json: bool,
+    /// protobuf data with a .proto schema per sheet
+    #[clap(long, short)]
+    proto: bool,
     #[clap(long, short, default_value_t = get_output_path().expect("failed to get default output path, try to specify manually"))]
     output_path: String,
     #[clap(long, short, value_parser = clap::value_parser ! (u32).range(0..12), default_value_t = 4)]
     compress_level: u32,
     #[clap(long, value_parser = clap::value_parser ! (tracing::Level), default_value = "info")]
     log_level: tracing::Level,
+    /// also export the client bundle to this dir, the server bundle is written to output_path
+    #[clap(long)]
+    client_output_path: Option<String>,
+    /// also write all errors as json, for ci
+    #[clap(long)]
+    report_json: Option<String>,
+    /// ignore the export manifest and export every workbook
     #[clap(long)]
-    client: bool,
+    full: bool,
+    /// commit id of the excel files, read from the git repository of input_path if not given
+    #[clap(long)]
+    commit_id: Option<String>,
+    /// export time in unix seconds written as create_mills, e.g. the commit time from `git log -1 --format=%ct`,
+    /// the current time if not given, give it to get the same bytes from the same configs
+    #[clap(long)]
+    build_time: Option<u64>,
+    /// also resolve enum and flags types against the enums of the .proto files in this dir, e.g. proto/src/proto
+    #[clap(long)]
+    proto_enum_path: Option<String>,
+    /// member of the proto Language enum the lang cells are written in, the default member if not given
+    #[clap(long)]
+    source_language: Option<String>,
+    /// server timezone of datetime cells without an offset, e.g. +08:00
+    #[clap(long, default_value = "UTC")]
+    timezone: String,
+}
+
+/// which configs need their generated files rewritten
+struct ExportChanges {
+    full: bool,
+    changed: HashSet<String>,
+    removed: BTreeSet<String>,
+}
+
+impl ExportChanges {
+    fn need_write(&self, name: &str, path: &Path) -> bool {
+        self.full || self.changed.contains(name) || !path.exists()
+    }
 }
 
 fn get_output_path() -> anyhow::Result<String> {
@@ -48,7 +113,9 @@ fn main() -> anyhow::Result<()> {
     let args = ExcelArgs::parse();
     init_logger(args.log_level).context("failed to init logger")?;
 
-    let excel_dir = WalkDir::new(&args.input_path);
+    rayon::ThreadPoolBuilder::new().num_threads(args.jobs).build_global().context("failed to build the worker pool")?;
+
+    let excel_dir = WalkDir::new(&args.input_path).sort_by_file_name();
     let mut all_excel_path = vec![];
     for dir in excel_dir {
         let dir = dir?;
@@ -57,207 +124,344 @@ fn main() -> anyhow::Result<()> {
         }
     }
 
+    let commit_id = match &args.commit_id {
+        Some(commit_id) => commit_id.clone(),
+        None => git_commit_id(&args.input_path).unwrap_or_else(|error| {
+            warn!("failed to read commit_id of {}: {:#}", args.input_path, error);
+            String::new()
+        }),
+    };
+    info!("commit_id: {}", commit_id);
+    let create_mills = match args.build_time {
+        Some(build_time) => build_time as u128 * 1000,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+    };
     let mut game_configs = GameConfigs {
+        commit_id,
         data: Vec::with_capacity(all_excel_path.len()),
-        create_mills: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+        create_mills,
         ..Default::default()
     };
+    let output_path = PathBuf::from(&args.output_path);
+    let manifest_path = output_path.join(MANIFEST_FILE);
+    let timezone = parse_timezone(&args.timezone)?;
+    let options = format!("version={},output_format={},commit_id={},format={:?},client_output_path={:?},lua={},lua_compact={},rust={},json={},proto={},timezone={}", env!("CARGO_PKG_VERSION"), OUTPUT_FORMAT_VERSION, game_configs.commit_id, args.format, args.client_output_path, args.lua, args.lua_compact, args.rust, args.json, args.proto, timezone);
+    let previous = if args.full {
+        ExportManifest::new(options.clone())
+    } else {
+        ExportManifest::load(&manifest_path, &options)
+    };
+    let mut manifest = ExportManifest::new(options);
+    let mut changed = HashSet::new();
+    let mut report = ErrorReport::default();
+    let mut inputs = vec![];
     for path in all_excel_path {
-        if let Some(ext) = path.extension() {
-            match ext.to_os_string().into_string() {
-                Ok(ext) => {
-                    if ext == "xlsx" {
-                        if let Some(config) = read_game_config(path, &args)? {
-                            game_configs.data.push(config);
-                        }
-                    } else {
-                        warn!("ignore files that are not of type xlsx: {}", path.display());
-                    }
-                }
-                Err(error) => {
-                    error!("failed to convert osString: {:?}", error);
-                }
-            };
+        if InputFormat::detect(&path).map(|format| { args.format.is_empty() || args.format.contains(&format) }).unwrap_or(false) {
+            inputs.push(path);
         } else {
-            warn!("ignore files without extensions: {}", path.display());
+            warn!("ignore files that are not of an input format: {}", path.display());
         }
     }
-    check_data_type(&game_configs)?;
-    write_to_bytes(&game_configs, &args)?;
-    generate_lua(&game_configs, &args)?;
-    Ok(())
-}
-
-fn read_game_config(path: PathBuf, arg: &ExcelArgs) -> anyhow::Result<Option<GameConfig>> {
-    let display_path = path.display().to_string();
-    info!("read: {}", display_path);
-    let mut workbook: Xlsx<_> = open_workbook(path).context(format!("open excel: {} failed", display_path))?;
-    let (sheet_name, data) = &workbook.worksheets()[0];
-    let mut cell_name = vec![];
-    let mut cell_type = vec![];
-    let mut key_type = vec![];
-    let mut excel_data = vec![];
-    for (i, row) in data.rows().enumerate() {
-        let mut row_data = vec![];
-        for data_type in row.iter() {
-            match i {
-                0 => {
-                    match data_type {
-                        DataType::String(data) => {
-                            cell_name.push(data.trim().to_string());
-                        }
-                        other => {
-                            return Err(anyhow!(format!("excel string expected, got: {}",other)));
-                        }
-                    }
-                }
-                1 => {
-                    match data_type {
-                        DataType::String(data) => {
-                            cell_type.push(CellType::from_str(data.trim())?);
-                        }
-                        other => {
-                            return Err(anyhow!(format!("excel string expected, got: {}",other)));
-                        }
-                    }
-                }
-                2 => {
-                    match data_type {
-                        DataType::String(data) => {
-                            key_type.push(KeyType::from_str(data.trim()).context(format!("convert string {} to enum KeyType error", data))?);
-                        }
-                        other => {
-                            return Err(anyhow!(format!("excel string expected, got: {}",other)));
-                        }
-                    }
-                }
-                3 | 4 => {}
-                _ => {
-                    row_data.push(data_type.to_string().trim().to_string());
+    // workers read in any order, the results are merged in input order so the report is the same as a sequential run
+    let reads = inputs.into_par_iter().map(|path| {
+        let display_path = path.display().to_string();
+        let mut input_report = ErrorReport::default();
+        let read = read_input(path, &previous, &mut input_report);
+        (display_path, read, input_report)
+    }).collect::<Vec<_>>();
+    for (display_path, read, input_report) in reads {
+        report.merge(input_report);
+        match read {
+            Ok(read) => {
+                if read.unchanged {
+                    info!("unchanged: {}", display_path);
+                } else {
+                    changed.extend(read.configs.iter().map(|config| { config.name.clone() }));
                 }
+                manifest.insert(&display_path, read.hash, &read.configs);
+                game_configs.data.extend(read.configs);
+            }
+            Err(error) => {
+                report.push(ExcelError::sheet(&display_path, "", format!("{:#}", error)).into());
             }
-        }
-        if i >= 5 {
-            excel_data.push(row_data);
         }
     }
-    let config = GameConfig::builder()
-        .name(sheet_name.clone())
-        .cell_name(cell_name)
-        .data(excel_data)
-        .cell_type(cell_type)
-        .key_type(key_type)
+    let mut bundle_paths = vec![(ExportSide::Server, output_path.clone())];
+    if let Some(client_output_path) = &args.client_output_path {
+        bundle_paths.push((ExportSide::Client, PathBuf::from(client_output_path)));
+    }
+    let export_options = ExportOptions::builder()
+        .proto_enum_path(args.proto_enum_path.clone())
+        .source_language(args.source_language.clone())
+        .timezone(timezone)
+        .sides(bundle_paths.iter().map(|(side, _)| { *side }).collect())
         .build();
-    let final_config = if !arg.client {
-        drop_client_data(config)
-    } else {
-        config
-    };
-    return if !final_config.key_type.is_empty() {
-        Ok(Some(final_config))
-    } else {
-        Ok(None)
-    };
+    let ExportDefines { enums, lang } = prepare_configs(&mut game_configs, &export_options, &mut report)?;
+    // enum values may change without the workbooks using them, so these configs are always rewritten
+    changed.extend(game_configs.data.iter().filter(|config| { config.cell_type.iter().any(|ty| { matches!(ty, CellType::Enum(_) | CellType::Flags(_)) }) }).map(|config| { config.name.clone() }));
+    let mut bundles = vec![];
+    for (side, path) in bundle_paths {
+        let bundle = game_configs.for_side(side);
+        report.extend(check_key(&bundle));
+        bundles.push((side, bundle, path));
+    }
+    if let Some(report_json) = &args.report_json {
+        report.write_json(report_json)?;
+        info!("error report write to: {}", report_json);
+    }
+    if report.is_empty().not() {
+        report.log();
+        return Err(anyhow!(format!("excel check failed with {} errors", report.len())));
+    }
+    write_lang_report(&lang, &output_path)?;
+    let removed: BTreeSet<String> = previous.config_names().difference(&manifest.config_names()).cloned().collect();
+    info!("{} configs changed, {} configs removed", changed.len(), removed.len());
+    for (side, mut bundle, path) in bundles {
+        bundle.digest = bundle.compute_digest()?;
+        info!("{} bundle digest: {}", side, bundle.digest);
+        // a sheet without columns of this side is not in the bundle, its old files are removed like a deleted sheet
+        let names = bundle.data.iter().map(|config| { config.name.as_str() }).collect::<HashSet<_>>();
+        let dropped = game_configs.data.iter().filter(|config| { !names.contains(config.name.as_str()) }).map(|config| { config.name.clone() });
+        let changes = ExportChanges {
+            full: previous.workbooks.is_empty(),
+            changed: changed.clone(),
+            removed: removed.iter().cloned().chain(dropped).collect(),
+        };
+        write_to_bytes(&bundle, &args, &path)?;
+        write_to_tables(&bundle, &args, &path)?;
+        generate_lua(&bundle, &enums, &args, &path, &changes)?;
+        generate_lang(&bundle, &lang, &args, &path)?;
+        generate_rust(&bundle, &enums, &args, &path, &changes)?;
+        generate_json(&bundle, &args, &path, &changes)?;
+        generate_proto(&bundle, &args, &path, &changes)?;
+    }
+    std::fs::create_dir_all(&output_path).context("failed to create dir")?;
+    manifest.save(&manifest_path)?;
+    Ok(())
 }
 
-fn check_data_type(config: &GameConfigs) -> anyhow::Result<()> {
+/// an input file read by a worker, configs of an unchanged file come from the previous manifest
+struct InputRead {
+    hash: String,
+    configs: Vec<GameConfig>,
+    unchanged: bool,
+}
+
+fn read_input(path: PathBuf, previous: &ExportManifest, report: &mut ErrorReport) -> anyhow::Result<InputRead> {
+    let display_path = path.display().to_string();
+    let hash = content_hash(&path)?;
+    if let Some(configs) = previous.configs(&display_path, &hash) {
+        return Ok(InputRead { hash, configs, unchanged: true });
+    }
+    let configs = read_workbook(path, report)?;
+    Ok(InputRead { hash, configs, unchanged: false })
+}
+
+fn check_key(config: &GameConfigs) -> Vec<anyhow::Error> {
     let mut errors = vec![];
-    let cell_checker = CellChecker;
     for config in &config.data {
-        for row in &config.data {
-            for (data, ty) in row.iter().zip(&config.cell_type) {
-                if let Some(error) = cell_checker.check((ty.clone(), data.clone())).err() {
-                    errors.push(error);
-                }
+        match config.lua_key_indexes() {
+            Ok(key_indexes) => {
+                errors.extend(config.check_keys(&key_indexes));
             }
+            Err(error) => {
+                errors.push(error);
+            }
+        }
+    }
+    errors
+}
+
+/// missing translations do not fail the export, they are listed per language in `missing_translations.json`
+fn write_lang_report(lang: &LangTexts, output_path: &Path) -> anyhow::Result<()> {
+    let file = output_path.join("missing_translations.json");
+    if lang.translations.is_empty() {
+        if file.exists() {
+            std::fs::remove_file(&file).context(format!("failed to remove: {}", file.display()))?;
         }
+        return Ok(());
     }
-    if errors.is_empty().not() {
-        for error in errors {
-            error!("{}",error);
+    let missing = lang.missing();
+    for language in lang.translations.keys() {
+        let count = missing.get(language).map(Vec::len).unwrap_or_default();
+        if count > 0 {
+            warn!("{} of {} lang texts are not translated to {}", count, lang.source.len(), language.as_str_name());
         }
-        return Err(anyhow!("excel data check failed"));
     }
+    for (key, source) in lang.unused() {
+        warn!("lang key {} translated in {} is not used", key, source);
+    }
+    let missing = missing.into_iter().map(|(language, keys)| { (language.as_str_name().to_string(), keys) }).collect::<BTreeMap<_, _>>();
+    std::fs::create_dir_all(output_path).context("failed to create dir")?;
+    std::fs::write(&file, serde_json::to_string_pretty(&missing)?).context(format!("failed to write: {}", file.display()))?;
     Ok(())
 }
 
-fn drop_client_data(config: GameConfig) -> GameConfig {
-    let config_builder = GameConfig::builder().name(config.name);
-    let mut server_key = HashMap::new();
-    let all_server_side = KeyType::server_side();
-    for (i, k) in config.key_type.iter().enumerate() {
-        if all_server_side.contains(&k) {
-            server_key.insert(i, k.clone());
-        }
+fn write_to_bytes(game_configs: &GameConfigs, args: &ExcelArgs, output_path: &Path) -> anyhow::Result<()> {
+    if args.bytes {
+        std::fs::create_dir_all(output_path).context("failed to create dir")?;
+        let path = output_path.join("config.bytes");
+        write_bundle(game_configs, args.compress_level, &path)?;
+        info!("config.bytes write to: {}",path.display());
     }
-    let mut server_cell_name = vec![];
-    for (i, n) in config.cell_name.into_iter().enumerate() {
-        if let Some(_) = server_key.get(&i) {
-            server_cell_name.push(n);
+    Ok(())
+}
+
+fn write_to_tables(game_configs: &GameConfigs, args: &ExcelArgs, output_path: &Path) -> anyhow::Result<()> {
+    if args.tables {
+        std::fs::create_dir_all(output_path).context("failed to create dir")?;
+        let path = output_path.join("config.tables");
+        write_tables(game_configs, &path)?;
+        // servers only check the header and index when they map the file, the crc is checked here once
+        MappedTables::open(&path)?.verify().context(format!("failed to verify: {}", path.display()))?;
+        info!("config.tables write to: {}",path.display());
+    }
+    Ok(())
+}
+
+fn generate_lua(game_configs: &GameConfigs, enums: &EnumDefines, args: &ExcelArgs, output_path: &Path, changes: &ExportChanges) -> anyhow::Result<()> {
+    if args.lua {
+        let path = output_path.join("lua");
+        prepare_output_dir(&path, changes, |name| { Ok(format!("{}.lua", name)) })?;
+        write_or_remove(&path.join(format!("{}.lua", ENUM_SHEET)), enums, || { Ok(format!("--- generated by excel_tool, do not edit\n{}", enums.to_lua()?)) })?;
+        for game_config in &game_configs.data {
+            let path = path.join(format!("{}.lua", game_config.name));
+            if !changes.need_write(&game_config.name, &path) {
+                continue;
+            }
+            let lua_code = format!("{}{}", lua_header(game_config, &game_configs.commit_id)?, game_config.write_lua(LuaOptions { compact: args.lua_compact })?);
+            let mut file = std::fs::File::create(path)?;
+            file.write(lua_code.as_bytes()).context("failed to write lua config")?;
         }
+        info!("generated lua write to: {}",path.display());
     }
-    let config_builder = config_builder.cell_name(server_cell_name);
+    Ok(())
+}
 
-    let mut server_key_type = vec![];
-    for (i, k) in config.key_type.into_iter().enumerate() {
-        if let Some(_) = server_key.get(&i) {
-            server_key_type.push(k);
+/// a json pack per language for the rust runtime, and a lua pack per language if lua is exported, always rewritten
+fn generate_lang(game_configs: &GameConfigs, lang: &LangTexts, args: &ExcelArgs, output_path: &Path) -> anyhow::Result<()> {
+    let json_path = output_path.join(LANG_DIR);
+    let lua_path = output_path.join("lua").join(LANG_DIR);
+    for path in [&json_path, &lua_path] {
+        if path.exists() {
+            std::fs::remove_dir_all(path).context(format!("failed to remove dir: {}", path.display()))?;
         }
     }
-    let config_builder = config_builder.key_type(server_key_type);
+    if lang.is_empty() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(&json_path).context("failed to create dir")?;
+    if args.lua {
+        std::fs::create_dir_all(&lua_path).context("failed to create dir")?;
+    }
+    for pack in lang.packs(game_configs) {
+        std::fs::write(json_path.join(format!("{}.json", pack.language)), serde_json::to_string_pretty(&pack)?).context(format!("failed to write lang pack: {}", pack.language))?;
+        if args.lua {
+            let lua_code = format!("--- generated by excel_tool, do not edit\n{}", pack.to_lua()?);
+            std::fs::write(lua_path.join(format!("{}.lua", pack.language)), lua_code).context(format!("failed to write lua lang pack: {}", pack.language))?;
+        }
+    }
+    info!("lang packs write to: {}", json_path.display());
+    Ok(())
+}
+
+/// the commit_id is part of the manifest options, so every file is rewritten when it changes and no header is stale
+fn lua_header(game_config: &GameConfig, commit_id: &str) -> anyhow::Result<String> {
+    let mut header = String::new();
+    header.push_str(&format!("{}\n", lua_comment(&format!("generated by excel_tool from {}, do not edit", game_config.source))));
+    header.push_str(&format!("--- commit_id: {}\n", commit_id));
+    header.push_str(&format!("--- digest: {}\n", game_config.digest()?));
+    Ok(header)
+}
 
-    let mut server_cell_type = vec![];
-    for (i, k) in config.cell_type.into_iter().enumerate() {
-        if let Some(_) = server_key.get(&i) {
-            server_cell_type.push(k);
+fn generate_rust(game_configs: &GameConfigs, enums: &EnumDefines, args: &ExcelArgs, output_path: &Path, changes: &ExportChanges) -> anyhow::Result<()> {
+    if args.rust {
+        let path = output_path.join("rust");
+        prepare_output_dir(&path, changes, |name| { Ok(format!("{}.rs", rust_module(name)?)) })?;
+        let mod_code = RustWriter::write_mod(game_configs, enums)?;
+        std::fs::write(path.join("mod.rs"), mod_code).context("failed to write rust mod")?;
+        write_or_remove(&path.join("enums.rs"), enums, || { RustWriter::write_enums(enums) })?;
+        for game_config in &game_configs.data {
+            let file = path.join(format!("{}.rs", rust_module(&game_config.name)?));
+            if !changes.need_write(&game_config.name, &file) {
+                continue;
+            }
+            let rust_code = RustWriter::write_config(game_config)?;
+            std::fs::write(file, rust_code).context(format!("failed to write rust config: {}", game_config.name))?;
         }
+        info!("generated rust write to: {}",path.display());
     }
-    let config_builder = config_builder.cell_type(server_cell_type);
+    Ok(())
+}
 
-    let mut server_cell_data = vec![];
-    for row in config.data {
-        let mut data = vec![];
-        for (i, d) in row.into_iter().enumerate() {
-            if let Some(_) = server_key.get(&i) {
-                data.push(d);
+fn generate_json(game_configs: &GameConfigs, args: &ExcelArgs, output_path: &Path, changes: &ExportChanges) -> anyhow::Result<()> {
+    if args.json {
+        let path = output_path.join("json");
+        prepare_output_dir(&path, changes, |name| { Ok(format!("{}.json", name)) })?;
+        for game_config in &game_configs.data {
+            let file = path.join(format!("{}.json", game_config.name));
+            if !changes.need_write(&game_config.name, &file) {
+                continue;
             }
+            let json = serde_json::to_string_pretty(&game_config.to_json()?).context(format!("failed to serialize json config: {}", game_config.name))?;
+            std::fs::write(file, json).context(format!("failed to write json config: {}", game_config.name))?;
         }
-        server_cell_data.push(data);
+        info!("generated json write to: {}",path.display());
     }
-    let config_builder = config_builder.data(server_cell_data);
+    Ok(())
+}
 
-    config_builder.build()
+/// schemas are written to `proto/schema` so the dir can be copied into the proto crate, data to `proto/data`
+fn generate_proto(game_configs: &GameConfigs, args: &ExcelArgs, output_path: &Path, changes: &ExportChanges) -> anyhow::Result<()> {
+    if args.proto {
+        let path = output_path.join("proto");
+        let schema_path = path.join("schema");
+        let data_path = path.join("data");
+        prepare_output_dir(&schema_path, changes, |name| { Ok(format!("{}.proto", proto_ident(name, Case::Snake)?)) })?;
+        prepare_output_dir(&data_path, changes, |name| { Ok(format!("{}.bytes", name)) })?;
+        std::fs::write(schema_path.join(PROTO_TYPES_FILE), ProtoWriter::write_types()).context("failed to write proto types")?;
+        for game_config in &game_configs.data {
+            let schema_file = schema_path.join(format!("{}.proto", proto_ident(&game_config.name, Case::Snake)?));
+            if changes.need_write(&game_config.name, &schema_file) {
+                std::fs::write(schema_file, ProtoWriter::write_schema(game_config)?).context(format!("failed to write proto schema: {}", game_config.name))?;
+            }
+            let data_file = data_path.join(format!("{}.bytes", game_config.name));
+            if changes.need_write(&game_config.name, &data_file) {
+                std::fs::write(data_file, ProtoWriter::write_data(game_config)?).context(format!("failed to write proto data: {}", game_config.name))?;
+            }
+        }
+        info!("generated proto write to: {}",path.display());
+    }
+    Ok(())
 }
 
-fn write_to_bytes(game_configs: &GameConfigs, args: &ExcelArgs) -> anyhow::Result<()> {
-    if args.bytes {
-        let encoded: Vec<u8> = bincode::serialize(&game_configs).context("failed to serialize GameConfigs")?;
-        info!("encoded: {}",encoded.len());
-        let path = PathBuf::from(args.output_path.clone());
-        std::fs::create_dir_all(&path).context("failed to create dir")?;
-        let path = path.join("config.bytes");
-        let path_display = path.display().to_string();
-        let mut encoder = EncoderBuilder::new().level(args.compress_level).build(File::create(path).context(format!("failed to create file: {}", path_display))?).context("failed to create EncoderBuilder")?;
-        encoder.write(&encoded)?;
-        info!("config.bytes write to: {}",path_display);
+/// the enum constants are written when there are enums and removed when the last enum is gone
+fn write_or_remove<F>(file: &Path, enums: &EnumDefines, content: F) -> anyhow::Result<()> where F: Fn() -> anyhow::Result<String> {
+    if !enums.is_empty() {
+        std::fs::write(file, content()?).context(format!("failed to write enums: {}", file.display()))?;
+    } else if file.exists() {
+        std::fs::remove_file(file).context(format!("failed to remove: {}", file.display()))?;
     }
     Ok(())
 }
 
-fn generate_lua(game_configs: &GameConfigs, args: &ExcelArgs) -> anyhow::Result<()> {
-    if args.lua {
-        let path = PathBuf::from(args.output_path.clone()).join("lua");
-        if path.exists() {
-            std::fs::remove_dir_all(&path).context("failed to remove dir")?;
-        }
-        std::fs::create_dir_all(&path).context("failed to create dir")?;
-        for game_config in &game_configs.data {
-            let path = path.join(format!("{}.lua", game_config.name));
-            let lua_code = game_config.to_lua()?;
-            let mut file = std::fs::File::create(path)?;
-            file.write(lua_code.as_bytes()).context("failed to write lua config")?;
+fn rust_module(name: &str) -> anyhow::Result<String> {
+    Ok(rust_ident(name, Case::Snake)?.trim_start_matches("r#").to_string())
+}
+
+/// a full export starts from an empty dir, otherwise only the files of removed configs are deleted
+fn prepare_output_dir<F>(path: &Path, changes: &ExportChanges, file_name: F) -> anyhow::Result<()> where F: Fn(&str) -> anyhow::Result<String> {
+    if changes.full && path.exists() {
+        std::fs::remove_dir_all(path).context("failed to remove dir")?;
+    }
+    std::fs::create_dir_all(path).context("failed to create dir")?;
+    if !changes.full {
+        for name in &changes.removed {
+            let file = path.join(file_name(name)?);
+            if file.exists() {
+                std::fs::remove_file(&file).context(format!("failed to remove: {}", file.display()))?;
+            }
         }
-        info!("generated lua write to: {}",path.display());
     }
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,240 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use lz4::EncoderBuilder;
+use sha2::{Digest, Sha256};
+
+use crate::excel::excel_define::GameConfigs;
+
+/// first bytes of every `config.bytes`
+pub const BUNDLE_MAGIC: [u8; 4] = *b"SDCB";
+/// version of the container layout, bumped when the header changes
+pub const BUNDLE_VERSION: u16 = 1;
+/// magic, version, schema hash, crc and uncompressed length
+pub const BUNDLE_HEADER_LEN: usize = 4 + 2 + 8 + 4 + 8;
+
+/// layout of the bincode payload, bincode writes no field names and enum variants by index,
+/// so this has to change with `GameConfigs`, `GameConfig` and every type they contain
+const BUNDLE_SCHEMA: &str = "GameConfigs{commit_id:String,create_mills:u128,data:Vec<GameConfig>,digest:String}\
+GameConfig{name:String,cell_name:Vec<String>,key_type:Vec<KeyType>,cell_type:Vec<CellType>,cell_ref:Vec<Option<CellRef>>,cell_rule:Vec<Vec<CellRule>>,data:Vec<Vec<String>>,side:ExportSide}\
+KeyType{AllKey,All,Client,ClientKey,Server,ServerKey}\
+CellType{UInt,Int,Long,String,Bool,Vector3ArrayInt,Vector3Int,Vector2Int,Vector3UInt,Vector2UInt,Vector2ArrayInt,ArrayInt,ArrayUInt,DictionaryStringFloat,DictionaryStringInt,Lang,Float,Double,Vector2Float,Vector3Float,Vector2String,Enum(String),Flags(String),Array(CellType),Struct(Vec<StructField>),DateTime,Duration,Cron}\
+StructField{name:String,ty:CellType}\
+CellRef{sheet:String,column:String}\
+CellRule{Range{min:Option<f64>,max:Option<f64>,inclusive:bool},NonEmpty,Enum(Vec<String>),Regex(String),Unique,Nullable,Default(String)}\
+ExportSide{All,Server,Client}";
+
+/// bundles are only read by a build with the same schema hash
+pub fn schema_hash() -> u64 {
+    let digest = Sha256::digest(BUNDLE_SCHEMA.as_bytes());
+    u64::from_le_bytes(digest[..8].try_into().expect("sha256 is longer than 8 bytes"))
+}
+
+/// header in front of the lz4 compressed bincode `GameConfigs`, numbers are little endian
+#[derive(Debug, PartialEq, Eq)]
+pub struct BundleHeader {
+    pub version: u16,
+    pub schema_hash: u64,
+    /// crc32 of the compressed payload
+    pub crc: u32,
+    pub uncompressed_len: u64,
+}
+
+impl BundleHeader {
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&BUNDLE_MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.schema_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.crc.to_le_bytes());
+        bytes.extend_from_slice(&self.uncompressed_len.to_le_bytes());
+    }
+
+    pub fn read(bytes: &[u8]) -> anyhow::Result<BundleHeader> {
+        if bytes.len() < BUNDLE_HEADER_LEN {
+            return Err(anyhow!(format!("not a config bundle, {} bytes is shorter than the header", bytes.len())));
+        }
+        if bytes[..4] != BUNDLE_MAGIC {
+            return Err(anyhow!(format!("not a config bundle, magic: {:?}", &bytes[..4])));
+        }
+        let header = BundleHeader {
+            version: u16::from_le_bytes(bytes[4..6].try_into()?),
+            schema_hash: u64::from_le_bytes(bytes[6..14].try_into()?),
+            crc: u32::from_le_bytes(bytes[14..18].try_into()?),
+            uncompressed_len: u64::from_le_bytes(bytes[18..26].try_into()?),
+        };
+        if header.version != BUNDLE_VERSION {
+            return Err(anyhow!(format!("unsupported config bundle version: {}, expect: {}", header.version, BUNDLE_VERSION)));
+        }
+        if header.schema_hash != schema_hash() {
+            return Err(anyhow!(format!("config bundle schema {:016x} is not compatible with {:016x}, export it with the excel_tool of this build", header.schema_hash, schema_hash())));
+        }
+        Ok(header)
+    }
+}
+
+/// the whole `config.bytes`, the same configs with the same `commit_id` and `create_mills` always give the same bytes,
+/// excel_tool takes `create_mills` from `--build-time` for that
+pub fn encode_bundle(game_configs: &GameConfigs, compress_level: u32) -> anyhow::Result<Vec<u8>> {
+    let encoded = bincode::serialize(game_configs).context("failed to serialize GameConfigs")?;
+    let mut encoder = EncoderBuilder::new().level(compress_level).build(vec![]).context("failed to create lz4 Encoder")?;
+    encoder.write_all(&encoded).context("failed to compress GameConfigs")?;
+    let (payload, result) = encoder.finish();
+    result.context("failed to compress GameConfigs")?;
+    let header = BundleHeader {
+        version: BUNDLE_VERSION,
+        schema_hash: schema_hash(),
+        crc: crc32fast::hash(&payload),
+        uncompressed_len: encoded.len() as u64,
+    };
+    let mut bytes = Vec::with_capacity(BUNDLE_HEADER_LEN + payload.len());
+    header.write(&mut bytes);
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+pub fn decode_bundle(bytes: &[u8]) -> anyhow::Result<GameConfigs> {
+    let header = BundleHeader::read(bytes)?;
+    let payload = &bytes[BUNDLE_HEADER_LEN..];
+    let crc = crc32fast::hash(payload);
+    if crc != header.crc {
+        return Err(anyhow!(format!("config bundle crc mismatch, expect: {:08x}, got: {:08x}, the file is corrupted or truncated", header.crc, crc)));
+    }
+    let decoder = lz4::Decoder::new(payload).context("failed to create lz4 Decoder")?;
+    let mut decoded = vec![];
+    // one more byte than expected so a longer payload is noticed
+    decoder.take(header.uncompressed_len + 1).read_to_end(&mut decoded).context("failed to decompress config bundle")?;
+    if decoded.len() as u64 != header.uncompressed_len {
+        return Err(anyhow!(format!("config bundle uncompressed length mismatch, expect: {}, got: {}", header.uncompressed_len, decoded.len())));
+    }
+    let game_configs: GameConfigs = bincode::deserialize(&decoded).context("failed to deserialize GameConfigs")?;
+    Ok(game_configs)
+}
+
+/// write `config.bytes`, readers see either the previous file or the complete new one
+pub fn write_bundle(game_configs: &GameConfigs, compress_level: u32, path: &Path) -> anyhow::Result<()> {
+    let bytes = encode_bundle(game_configs, compress_level)?;
+    write_atomic(path, &bytes)
+}
+
+/// write to a temp file next to `path` and rename it over `path`
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let file_name = path.file_name().ok_or(anyhow!(format!("not a file path: {}", path.display())))?;
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id()));
+    let result = (|| -> anyhow::Result<()> {
+        let mut file = std::fs::File::create(&temp_path).context(format!("failed to create file: {}", temp_path.display()))?;
+        file.write_all(bytes).context(format!("failed to write: {}", temp_path.display()))?;
+        file.sync_all().context(format!("failed to sync: {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, path).context(format!("failed to rename {} to {}", temp_path.display(), path.display()))
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use strum::IntoEnumIterator;
+
+    use crate::excel::bundle::{BUNDLE_HEADER_LEN, decode_bundle, encode_bundle, schema_hash, write_bundle};
+    use crate::excel::config_loader::read_game_configs;
+    use crate::excel::excel_define::{CellRef, CellRule, CellType, ExportSide, GameConfig, GameConfigs, KeyType, StructField};
+    use crate::excel::revision::sha256_hex;
+
+    #[test]
+    fn test_bundle() -> anyhow::Result<()> {
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "name".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::String])
+            .data(vec![vec!["1001".to_string(), "sword".to_string()]])
+            .build();
+        let game_configs = GameConfigs::builder().commit_id("abc".to_string()).create_mills(1).data(vec![config]).build();
+        let bytes = encode_bundle(&game_configs, 4)?;
+        assert_eq!(&bytes[..4], b"SDCB");
+        assert_eq!(bytes, encode_bundle(&game_configs, 4)?);
+        assert_eq!(decode_bundle(&bytes)?.data[0].data, game_configs.data[0].data);
+
+        let path = env::temp_dir().join("test_bundle_config.bytes");
+        write_bundle(&game_configs, 4, &path)?;
+        assert_eq!(read_game_configs(&path)?.commit_id, "abc");
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(decode_bundle(&corrupted).unwrap_err().to_string().contains("crc mismatch"));
+        assert!(decode_bundle(&bytes[..bytes.len() - 1]).unwrap_err().to_string().contains("crc mismatch"));
+        assert!(decode_bundle(&bytes[..BUNDLE_HEADER_LEN - 1]).unwrap_err().to_string().contains("shorter than the header"));
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert!(decode_bundle(&version).unwrap_err().to_string().contains("unsupported config bundle version: 2"));
+        let mut schema = bytes.clone();
+        schema[6] ^= 1;
+        assert!(decode_bundle(&schema).unwrap_err().to_string().contains("not compatible"));
+        let mut magic = bytes;
+        magic[0] = b'X';
+        assert!(decode_bundle(&magic).unwrap_err().to_string().contains("not a config bundle"));
+        Ok(())
+    }
+
+    /// a value of every variant of the bundle types, the encoding of it is pinned with the schema hash,
+    /// when the types change this fails until `BUNDLE_SCHEMA` and both pinned values are updated
+    #[test]
+    fn test_bundle_schema() -> anyhow::Result<()> {
+        let struct_type = CellType::Struct(vec![StructField { name: "id".to_string(), ty: CellType::UInt }]);
+        let cell_types = vec![
+            CellType::UInt, CellType::Int, CellType::Long, CellType::String, CellType::Bool, CellType::Vector3ArrayInt, CellType::Vector3Int,
+            CellType::Vector2Int, CellType::Vector3UInt, CellType::Vector2UInt, CellType::Vector2ArrayInt, CellType::ArrayInt, CellType::ArrayUInt,
+            CellType::DictionaryStringFloat, CellType::DictionaryStringInt, CellType::Lang, CellType::Float, CellType::Double, CellType::Vector2Float,
+            CellType::Vector3Float, CellType::Vector2String, CellType::Enum("Quality".to_string()), CellType::Flags("Element".to_string()),
+            CellType::Array(Box::new(struct_type.clone())), struct_type, CellType::DateTime, CellType::Duration, CellType::Cron,
+        ];
+        for ty in &cell_types {
+            // a new variant fails to compile here, add it to `cell_types`
+            match ty {
+                CellType::UInt | CellType::Int | CellType::Long | CellType::String | CellType::Bool | CellType::Vector3ArrayInt | CellType::Vector3Int
+                | CellType::Vector2Int | CellType::Vector3UInt | CellType::Vector2UInt | CellType::Vector2ArrayInt | CellType::ArrayInt | CellType::ArrayUInt
+                | CellType::DictionaryStringFloat | CellType::DictionaryStringInt | CellType::Lang | CellType::Float | CellType::Double | CellType::Vector2Float
+                | CellType::Vector3Float | CellType::Vector2String | CellType::Enum(_) | CellType::Flags(_) | CellType::Array(_) | CellType::Struct(_)
+                | CellType::DateTime | CellType::Duration | CellType::Cron => {}
+            }
+        }
+        let cell_rules = vec![
+            CellRule::Range { min: Some(1.0), max: None, inclusive: true }, CellRule::NonEmpty, CellRule::Enum(vec!["a".to_string()]),
+            CellRule::Regex("^a$".to_string()), CellRule::Unique, CellRule::Nullable, CellRule::Default("1".to_string()),
+        ];
+        for rule in &cell_rules {
+            // a new variant fails to compile here, add it to `cell_rules`
+            match rule {
+                CellRule::Range { .. } | CellRule::NonEmpty | CellRule::Enum(_) | CellRule::Regex(_) | CellRule::Unique | CellRule::Nullable | CellRule::Default(_) => {}
+            }
+        }
+        let sides = vec![ExportSide::All, ExportSide::Server, ExportSide::Client];
+        for side in &sides {
+            // a new variant fails to compile here, add it to `sides`
+            match side {
+                ExportSide::All | ExportSide::Server | ExportSide::Client => {}
+            }
+        }
+        let data = sides.into_iter().map(|side| {
+            GameConfig::builder()
+                .name("item".to_string())
+                .cell_name(vec!["id".to_string()])
+                .key_type(KeyType::iter().collect())
+                .cell_type(cell_types.clone())
+                .cell_ref(vec![None, Some(CellRef { sheet: "item".to_string(), column: "id".to_string() })])
+                .cell_rule(vec![cell_rules.clone()])
+                .data(vec![vec!["1".to_string()]])
+                .side(side)
+                .build()
+        }).collect();
+        let game_configs = GameConfigs::builder().commit_id("abc".to_string()).create_mills(1).data(data).digest("def".to_string()).build();
+        let encoded = sha256_hex(&bincode::serialize(&game_configs)?);
+        assert_eq!(encoded, "cb603f892a46a564c865178b36b1c6a6422f7e9d987468eba4874a7efe1a8e3a", "the bincode layout changed, update BUNDLE_SCHEMA and the pinned values");
+        assert_eq!(format!("{:016x}", schema_hash()), "c2ef46df23377d14");
+        Ok(())
+    }
+}
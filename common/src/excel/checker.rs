@@ -1,13 +1,16 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use mlua::chunk;
-use tracing::trace;
+use tracing::{info, trace};
 
+use crate::excel::composite::CompositeValue;
 use crate::excel::convert::*;
-use crate::excel::excel_define::CellType;
-use crate::init_logger;
-use crate::lua_helper::RustUtil;
+use crate::excel::enum_define::enum_tokens;
+use crate::excel::excel_define::{CellRule, CellType, GameConfig, GameConfigs};
+use crate::excel::report::{CellLocation, ErrorReport, ExcelError};
+use crate::lua_helper::{new_lua_state, take_lua_errors};
 
 #[macro_export]
 macro_rules! parse {
@@ -27,11 +30,17 @@ pub trait Checker {
 pub struct CellChecker;
 
 impl Checker for CellChecker {
-    type Input = (CellType, String);
+    type Input = (CellType, String, CellLocation);
     type Output = ();
 
     fn check(&self, input: Self::Input) -> anyhow::Result<Self::Output> {
-        let (ty, data) = input;
+        let (ty, data, location) = input;
+        Self::check_cell(&ty, &data).map_err(|error| { ExcelError::at(&location, &data, error).into() })
+    }
+}
+
+impl CellChecker {
+    fn check_cell(ty: &CellType, data: &String) -> anyhow::Result<()> {
         match ty {
             CellType::UInt => {
                 let parsed = parse!(data,u32);
@@ -86,10 +95,12 @@ impl Checker for CellChecker {
                 trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
             }
             CellType::DictionaryStringFloat => {
-                unimplemented!("DictionaryStringFloat")
+                let parsed = parse!(data,DictionaryStringFloat);
+                trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
             }
             CellType::DictionaryStringInt => {
-                unimplemented!("DictionaryStringInt")
+                let parsed = parse!(data,DictionaryStringInt);
+                trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
             }
             CellType::Lang => {
                 let parsed = parse!(data,String);
@@ -115,30 +126,401 @@ impl Checker for CellChecker {
                 let parsed = parse!(data,Vector2String);
                 trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
             }
+            CellType::Enum(_) | CellType::Flags(_) => {
+                let parsed = enum_tokens(ty, data)?;
+                trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
+            }
+            CellType::Array(_) | CellType::Struct(_) => {
+                let parsed = CompositeValue::parse(ty, data)?;
+                trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
+            }
+            CellType::DateTime => {
+                let parsed = parse!(data,DateTime);
+                trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
+            }
+            CellType::Duration => {
+                let parsed = parse!(data,Duration);
+                trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
+            }
+            CellType::Cron => {
+                let parsed = parse!(data,CronSchedule);
+                trace!("key: {}, parse data: {:?} to {:?}",ty,data,parsed);
+            }
         }
         Ok(())
     }
 }
 
+/// enforces the rules of one column, every cell is checked against every rule
+pub struct RuleChecker;
+
+impl Checker for RuleChecker {
+    type Input = (CellType, Vec<CellRule>, Vec<(String, CellLocation)>);
+    type Output = Vec<anyhow::Error>;
+
+    fn check(&self, input: Self::Input) -> anyhow::Result<Self::Output> {
+        let (ty, rules, cells) = input;
+        let mut errors = vec![];
+        for rule in &rules {
+            if !rule.accept(&ty) {
+                return Err(anyhow::anyhow!(format!("rule {} cannot be used with cell type {}", rule, ty)));
+            }
+            match rule {
+                CellRule::Unique => {
+                    let mut first_rows: HashMap<String, usize> = HashMap::new();
+                    for (data, location) in &cells {
+                        if data.is_empty() {
+                            continue;
+                        }
+                        // enum cells are compared as written, they are resolved to numbers after the check
+                        let value = match ty {
+                            CellType::Enum(_) | CellType::Flags(_) => data.clone(),
+                            _ => JsonWriter::write(&ty, data)?.to_string(),
+                        };
+                        match first_rows.get(&value) {
+                            None => {
+                                first_rows.insert(value, location.row);
+                            }
+                            Some(first_row) => {
+                                errors.push(ExcelError::at(location, data, format!("duplicate value of unique column, first defined at row {}", first_row)).into());
+                            }
+                        }
+                    }
+                }
+                CellRule::Nullable => {}
+                CellRule::Default(value) => {
+                    if rules.contains(&CellRule::Nullable) {
+                        return Err(anyhow::anyhow!(format!("rule {} cannot be used with nullable", rule)));
+                    }
+                    CellChecker::check_cell(&ty, value).map_err(|error| { anyhow::anyhow!(format!("incorrect {}: {}", rule, error)) })?;
+                }
+                CellRule::Regex(pattern) => {
+                    let regex = regex::Regex::new(pattern)?;
+                    for (data, location) in &cells {
+                        if !data.is_empty() && !regex.is_match(data) {
+                            errors.push(ExcelError::at(location, data, format!("does not match {}", rule)).into());
+                        }
+                    }
+                }
+                _ => {
+                    for (data, location) in &cells {
+                        if let Err(error) = Self::check_cell(&ty, rule, data) {
+                            errors.push(ExcelError::at(location, data, error).into());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(errors)
+    }
+}
+
+impl RuleChecker {
+    fn check_cell(ty: &CellType, rule: &CellRule, data: &String) -> anyhow::Result<()> {
+        if data.is_empty() {
+            return if *rule == CellRule::NonEmpty {
+                Err(anyhow::anyhow!("cell is empty"))
+            } else {
+                Ok(())
+            };
+        }
+        if *rule == CellRule::NonEmpty {
+            return Ok(());
+        }
+        let mut values = vec![];
+        leaf_values(JsonWriter::write(ty, data)?, &mut values);
+        for value in values {
+            match rule {
+                CellRule::Range { min, max, inclusive } => {
+                    let number = value.as_f64().unwrap_or_default();
+                    let below = min.map(|min| { number < min }).unwrap_or(false);
+                    let above = max.map(|max| { if *inclusive { number > max } else { number >= max } }).unwrap_or(false);
+                    if below || above {
+                        return Err(anyhow::anyhow!(format!("{} out of {}", value, rule)));
+                    }
+                }
+                CellRule::Enum(allowed) => {
+                    let value = match value {
+                        serde_json::Value::String(value) => value,
+                        other => other.to_string(),
+                    };
+                    if !allowed.contains(&value) {
+                        return Err(anyhow::anyhow!(format!("{} not in {}", value, rule)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// elements of arrays and vectors and the values of dictionaries
+fn leaf_values(value: serde_json::Value, values: &mut Vec<serde_json::Value>) {
+    match value {
+        serde_json::Value::Array(array) => {
+            for v in array {
+                leaf_values(v, values);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (_, v) in map {
+                leaf_values(v, values);
+            }
+        }
+        other => values.push(other),
+    }
+}
+
+/// check every cell of the config and the column rules, returning all errors instead of the first one
+pub fn check_game_config(config: &GameConfig) -> Vec<anyhow::Error> {
+    let mut invalid_cells = HashSet::new();
+    let mut errors = check_cells(config, &mut invalid_cells);
+    let rule_checker = RuleChecker;
+    for (j, ty) in config.cell_type.iter().enumerate() {
+        let mut rules = config.cell_rule.get(j).cloned().unwrap_or_default();
+        // empty cells only mean "use the default" when the column says so
+        if !ty.has_empty_value() && !config.nullable(j) && config.default_value(j).is_none() && !rules.contains(&CellRule::NonEmpty) {
+            rules.push(CellRule::NonEmpty);
+        }
+        if rules.is_empty() {
+            continue;
+        }
+        // cells that cannot be parsed are already reported by the type check
+        let cells = config.data.iter().enumerate().filter(|(i, _)| { !invalid_cells.contains(&(*i, j)) }).map(|(i, row)| { (row[j].clone(), config.cell_location(i, j)) }).collect();
+        match rule_checker.check((ty.clone(), rules, cells)) {
+            Ok(rule_errors) => errors.extend(rule_errors),
+            Err(error) => errors.push(config.column_error(j, error)),
+        }
+    }
+    errors
+}
+
+/// check every cell of an exported config, the rules were checked on the source text before datetime, duration,
+/// enum and lang cells were resolved, so they are not checked again
+pub fn check_exported_config(config: &GameConfig) -> Vec<anyhow::Error> {
+    check_cells(config, &mut HashSet::new())
+}
+
+fn check_cells(config: &GameConfig, invalid_cells: &mut HashSet<(usize, usize)>) -> Vec<anyhow::Error> {
+    let mut errors = vec![];
+    let cell_checker = CellChecker;
+    for (i, row) in config.data.iter().enumerate() {
+        for (j, (data, ty)) in row.iter().zip(&config.cell_type).enumerate() {
+            if let Some(error) = cell_checker.check((ty.clone(), data.clone(), config.cell_location(i, j))).err() {
+                errors.push(error);
+                invalid_cells.insert((i, j));
+            }
+        }
+    }
+    errors
+}
+
+/// values of a cell that reference another sheet: scalars themselves, array elements, the first component
+/// of vectors and the keys of dictionaries, empty cells reference nothing while `0` is an ordinary value
+pub fn reference_values(ty: &CellType, data: &String) -> anyhow::Result<Vec<String>> {
+    if data.is_empty() {
+        return Ok(vec![]);
+    }
+    let values = match ty {
+        CellType::UInt => vec![parse!(data,u32).to_string()],
+        CellType::Int => vec![parse!(data,i32).to_string()],
+        CellType::Long => vec![parse!(data,i64).to_string()],
+        CellType::String | CellType::Lang => vec![data.clone()],
+        CellType::ArrayInt => parse!(data,ArrayInt).iter().map(ToString::to_string).collect(),
+        CellType::ArrayUInt => parse!(data,ArrayUInt).iter().map(ToString::to_string).collect(),
+        CellType::Vector2Int => vec![parse!(data,Vector2Int).0.to_string()],
+        CellType::Vector3Int => vec![parse!(data,Vector3Int).0.to_string()],
+        CellType::Vector2UInt => vec![parse!(data,Vector2UInt).0.to_string()],
+        CellType::Vector3UInt => vec![parse!(data,Vector3UInt).0.to_string()],
+        CellType::Vector2String => vec![parse!(data,Vector2String).0],
+        CellType::Vector2ArrayInt => parse!(data,Vector2ArrayInt).iter().map(|v| { v.0.to_string() }).collect(),
+        CellType::Vector3ArrayInt => parse!(data,Vector3ArrayInt).iter().map(|v| { v.0.to_string() }).collect(),
+        CellType::DictionaryStringFloat => parse!(data,DictionaryStringFloat).into_keys().collect(),
+        CellType::DictionaryStringInt => parse!(data,DictionaryStringInt).into_keys().collect(),
+        CellType::Bool | CellType::Float | CellType::Double | CellType::Vector2Float | CellType::Vector3Float | CellType::Enum(_) | CellType::Flags(_) | CellType::Array(_) | CellType::Struct(_) | CellType::DateTime | CellType::Duration | CellType::Cron => {
+            return Err(anyhow::anyhow!(format!("cell type {} cannot reference another sheet", ty)));
+        }
+    };
+    Ok(values.into_iter().filter(|v| { !v.is_empty() }).collect())
+}
+
+/// cross sheet pass: every referencing cell must point at an existing value of the referenced column
+pub fn check_references(configs: &GameConfigs) -> Vec<anyhow::Error> {
+    let mut errors = vec![];
+    let mut referenced: HashMap<String, HashSet<String>> = HashMap::new();
+    for config in &configs.data {
+        for (column, cell_ref) in config.cell_ref.iter().enumerate() {
+            let cell_ref = match cell_ref {
+                None => continue,
+                Some(cell_ref) => cell_ref,
+            };
+            let ty = &config.cell_type[column];
+            let ref_key = cell_ref.to_string();
+            if !referenced.contains_key(&ref_key) {
+                let target = configs.data.iter().find(|c| { c.name == cell_ref.sheet });
+                let target = match target {
+                    None => {
+                        errors.push(config.column_error(column, format!("{} sheet not found", cell_ref)));
+                        continue;
+                    }
+                    Some(target) => target,
+                };
+                let target_index = match target.cell_name.iter().position(|n| { *n == cell_ref.column }) {
+                    None => {
+                        errors.push(config.column_error(column, format!("{} column not found", cell_ref)));
+                        continue;
+                    }
+                    Some(index) => index,
+                };
+                let mut values = HashSet::new();
+                for row in &target.data {
+                    let data = &row[target_index];
+                    match reference_values(&target.cell_type[target_index], data) {
+                        Ok(v) => values.extend(v),
+                        Err(_) => {
+                            values.insert(data.clone());
+                        }
+                    }
+                }
+                referenced.insert(ref_key.clone(), values);
+            }
+            let values = &referenced[&ref_key];
+            for (i, row) in config.data.iter().enumerate() {
+                let data = &row[column];
+                // cells that cannot be parsed are already reported by the type check
+                if let Ok(refs) = reference_values(ty, data) {
+                    for r in refs {
+                        if !values.contains(&r) {
+                            errors.push(config.cell_error(i, column, format!("{} not found in {}", r, cell_ref)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    errors
+}
+
 pub struct LuaChecker;
 
 impl Checker for LuaChecker {
     type Input = String;
-    type Output = ();
+    /// errors reported through `RustUtil.ReportError`, a failing entry is reported as well so all problems show up at once
+    type Output = ErrorReport;
 
     fn check(&self, input: Self::Input) -> anyhow::Result<Self::Output> {
-        init_logger(tracing::Level::INFO).context("failed to init logger")?;
-        let lua = mlua::Lua::new();
-        let rust_util = lua.create_proxy::<RustUtil>()?;
-        lua.globals().set("RustUtil", rust_util)?;
-        println!("{}",input);
+        let lua = new_lua_state()?;
+        info!("check lua entry: {}", input);
         lua.load(chunk!{
             package.path = "common/?.lua"
         }).exec()?;
         let current_dir = env::current_dir()?;
-        let init_path = current_dir.join(input);
-        let init = std::fs::read_to_string(init_path)?;
-        lua.load(&init).exec()?;
+        let init_path = current_dir.join(&input);
+        let init = std::fs::read_to_string(&init_path).context(format!("failed to read lua entry: {}", init_path.display()))?;
+        let result = lua.load(&init).exec();
+        let mut report = take_lua_errors(&lua);
+        if let Err(error) = result {
+            report.push(anyhow!(format!("failed to execute lua entry {}: {}", input, error)));
+        }
+        Ok(report)
+    }
+}
+#[cfg(test)]
+mod test {
+    use crate::excel::checker::{check_game_config, check_references};
+    use crate::excel::convert::ToLua;
+    use crate::excel::excel_define::{CellRef, CellRule, CellType, GameConfig, GameConfigs, KeyType};
+
+    #[test]
+    fn test_check_references() {
+        let item = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string()])
+            .key_type(vec![KeyType::AllKey])
+            .cell_type(vec![CellType::UInt])
+            .cell_ref(vec![None])
+            .data(vec![vec!["1001".to_string()], vec!["1002".to_string()]])
+            .build();
+        let reward = GameConfig::builder()
+            .name("reward".to_string())
+            .cell_name(vec!["id".to_string(), "items".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::Vector2ArrayInt])
+            .cell_ref(vec![None, Some("ref:item.id".parse::<CellRef>().unwrap())])
+            .data(vec![vec!["1".to_string(), "1001,5;1002,1".to_string()], vec!["2".to_string(), "1003,1".to_string()], vec!["3".to_string(), "0,1".to_string()], vec!["4".to_string(), "".to_string()]])
+            .build();
+        let configs = GameConfigs::builder().commit_id(String::new()).create_mills(0).data(vec![item, reward]).build();
+        let errors = check_references(&configs);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].to_string(), r#" [reward] B7 items(vector2_array_int) data: "1003,1": 1003 not found in ref:item.id"#);
+        assert_eq!(errors[1].to_string(), r#" [reward] B8 items(vector2_array_int) data: "0,1": 0 not found in ref:item.id"#);
+    }
+
+    #[test]
+    fn test_check_rules() -> anyhow::Result<()> {
+        let level = GameConfig::builder()
+            .name("level".to_string())
+            .cell_name(vec!["id".to_string(), "exp".to_string(), "icon".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::ArrayInt, CellType::String])
+            .cell_rule(vec![
+                CellRule::parse_rules("unique;non_empty")?,
+                CellRule::parse_rules("range:1..=100")?,
+                CellRule::parse_rules("enum:a.png,b.png;regex:^[a-z]+\\.png$")?,
+            ])
+            .data(vec![
+                vec!["1".to_string(), "1,100".to_string(), "a.png".to_string()],
+                vec!["1".to_string(), "0,101".to_string(), "c.png".to_string()],
+                vec!["".to_string(), "x".to_string(), "A.png".to_string()],
+            ])
+            .build();
+        let errors = check_game_config(&level).iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors, vec![
+            r#" [level] B8 exp(array_int) data: "x": invalid digit found in string"#,
+            r#" [level] A7 id(uint) data: "1": duplicate value of unique column, first defined at row 6"#,
+            r#" [level] A8 id(uint) data: "": cell is empty"#,
+            r#" [level] B7 exp(array_int) data: "0,101": 0 out of range:1..=100"#,
+            r#" [level] C7 icon(string) data: "c.png": c.png not in enum:a.png,b.png"#,
+            r#" [level] C8 icon(string) data: "A.png": A.png not in enum:a.png,b.png"#,
+            r#" [level] C8 icon(string) data: "A.png": does not match regex:^[a-z]+\.png$"#,
+        ]);
+        assert!(CellRule::parse_rules("range:a..b").is_err());
+        assert!(CellRule::parse_rules("between:1,2").is_err());
+        assert_eq!(CellRule::parse_rules("regex:^(a;b)$")?, vec![CellRule::Regex("^(a;b)$".to_string())]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_cells() -> anyhow::Result<()> {
+        let mut level = GameConfig::builder()
+            .name("level".to_string())
+            .cell_name(vec!["id".to_string(), "open".to_string(), "exp".to_string(), "pos".to_string(), "icon".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::Bool, CellType::UInt, CellType::Vector2String, CellType::String])
+            .cell_rule(vec![vec![], CellRule::parse_rules("nullable")?, CellRule::parse_rules("default:10;x")?, vec![], vec![]])
+            .data(vec![
+                vec!["1".to_string(), "0".to_string(), "".to_string(), "0".to_string(), "".to_string()],
+                vec!["2".to_string(), "".to_string(), "5".to_string(), "".to_string(), "".to_string()],
+            ])
+            .build();
+        let errors = check_game_config(&level).iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors, vec![
+            r#" [level] D6 pos(vector[string,string]) data: "0": incorrect cell data: 0"#,
+            r#" [level] C exp(uint): incorrect default:10;x: invalid digit found in string"#,
+            r#" [level] D7 pos(vector[string,string]) data: "": cell is empty"#,
+        ]);
+
+        level.cell_rule[2] = CellRule::parse_rules("default:10")?;
+        level.data[0][3] = "a,b".to_string();
+        level.data[1][3] = "c,d".to_string();
+        assert!(check_game_config(&level).is_empty());
+        level.apply_defaults();
+        assert_eq!(level.data[0][2], "10");
+        let rows = level.to_json()?["rows"].clone();
+        assert_eq!(rows[0]["open"], serde_json::json!(false));
+        assert_eq!(rows[1]["open"], serde_json::Value::Null);
+        assert!(level.to_lua()?.contains("[2] = { [1] = 2, [2] = nil, [3] = 5"));
         Ok(())
     }
 }
\ No newline at end of file
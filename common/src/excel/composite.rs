@@ -0,0 +1,367 @@
+use anyhow::anyhow;
+
+use crate::excel::convert::{Parse, ToLua};
+use crate::excel::excel_define::CellType;
+
+/// parsed cell of an `array<..>` or `struct{..}` column
+///
+/// arrays are written as `[a,b]` and structs list their fields in order as `{a,b}`, the outermost brackets may be
+/// left out, e.g. `{1001,2},{1002,5}` for `array<struct{id:uint,count:int}>`. strings may be quoted as `"a,b"` and
+/// `\` escapes the next character, so separators, brackets and quotes can be part of a string.
+/// an empty cell, an empty value and missing trailing struct fields take the default value
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompositeValue {
+    UInt(u32),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<CompositeValue>),
+    Struct(Vec<(String, CompositeValue)>),
+}
+
+impl CompositeValue {
+    pub fn parse(ty: &CellType, data: &str) -> anyhow::Result<Self> {
+        let data = data.trim();
+        let (open, close) = match ty {
+            CellType::Array(_) => ('[', ']'),
+            CellType::Struct(_) => ('{', '}'),
+            other => return Err(anyhow!(format!("{} is not a composite type", other))),
+        };
+        if data.is_empty() {
+            return Self::default_of(ty);
+        }
+        match ValueParser::new(data).parse(ty) {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                let wrapped = format!("{}{}{}", open, data, close);
+                match ValueParser::new(&wrapped).parse(ty) {
+                    Ok(value) => Ok(value),
+                    Err(_) if data.starts_with(open) => Err(error),
+                    Err(wrapped_error) => Err(wrapped_error),
+                }
+            }
+        }
+    }
+
+    pub fn default_of(ty: &CellType) -> anyhow::Result<Self> {
+        let value = match ty {
+            CellType::UInt => CompositeValue::UInt(0),
+            CellType::Int => CompositeValue::Int(0),
+            CellType::Long => CompositeValue::Long(0),
+            CellType::Float => CompositeValue::Float(0.0),
+            CellType::Double => CompositeValue::Double(0.0),
+            CellType::Bool => CompositeValue::Bool(false),
+            CellType::String | CellType::Lang => CompositeValue::String(String::new()),
+            CellType::Array(_) => CompositeValue::Array(vec![]),
+            CellType::Struct(fields) => {
+                let mut values = Vec::with_capacity(fields.len());
+                for field in fields {
+                    values.push((field.name.clone(), Self::default_of(&field.ty)?));
+                }
+                CompositeValue::Struct(values)
+            }
+            other => return Err(anyhow!(format!("{} cannot be nested in array or struct", other))),
+        };
+        Ok(value)
+    }
+
+    /// cell text that parses back to the same value, strings are quoted
+    pub fn to_cell(&self) -> String {
+        match self {
+            CompositeValue::UInt(value) => value.to_string(),
+            CompositeValue::Int(value) => value.to_string(),
+            CompositeValue::Long(value) => value.to_string(),
+            CompositeValue::Float(value) => value.to_string(),
+            CompositeValue::Double(value) => value.to_string(),
+            CompositeValue::Bool(value) => value.to_string(),
+            CompositeValue::String(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+            CompositeValue::Array(values) => format!("[{}]", values.iter().map(CompositeValue::to_cell).collect::<Vec<_>>().join(",")),
+            CompositeValue::Struct(fields) => format!("{{{}}}", fields.iter().map(|(_, value)| { value.to_cell() }).collect::<Vec<_>>().join(",")),
+        }
+    }
+
+    /// strings typed `lang` with their path of array indexes and struct field names joined by `.`, e.g. `0.name`
+    pub fn lang_texts(&mut self, ty: &CellType) -> Vec<(String, &mut String)> {
+        let mut texts = vec![];
+        self.collect_lang_texts(ty, String::new(), &mut texts);
+        texts
+    }
+
+    fn collect_lang_texts<'a>(&'a mut self, ty: &CellType, path: String, texts: &mut Vec<(String, &'a mut String)>) {
+        let child = |name: &str| -> String {
+            if path.is_empty() { name.to_string() } else { format!("{}.{}", path, name) }
+        };
+        match (self, ty) {
+            (CompositeValue::String(text), CellType::Lang) => texts.push((path, text)),
+            (CompositeValue::Array(values), CellType::Array(element)) => {
+                for (i, value) in values.iter_mut().enumerate() {
+                    value.collect_lang_texts(element, child(&i.to_string()), texts);
+                }
+            }
+            (CompositeValue::Struct(values), CellType::Struct(fields)) => {
+                for ((name, value), field) in values.iter_mut().zip(fields) {
+                    value.collect_lang_texts(&field.ty, child(name), texts);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct ValueParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ValueParser {
+    fn new(data: &str) -> Self {
+        Self {
+            chars: data.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn parse(mut self, ty: &CellType) -> anyhow::Result<CompositeValue> {
+        let value = self.value(ty)?;
+        self.skip_whitespace();
+        if self.pos < self.chars.len() {
+            return Err(self.error("unexpected character"));
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self, ty: &CellType) -> anyhow::Result<CompositeValue> {
+        match ty {
+            CellType::Array(element) => {
+                self.expect('[')?;
+                let mut values = vec![];
+                if !self.eat(']') {
+                    loop {
+                        values.push(self.value(element)?);
+                        if !self.eat(',') {
+                            break;
+                        }
+                    }
+                    self.expect(']')?;
+                }
+                Ok(CompositeValue::Array(values))
+            }
+            CellType::Struct(fields) => {
+                self.expect('{')?;
+                let mut values = Vec::with_capacity(fields.len());
+                if !self.eat('}') {
+                    loop {
+                        let field = fields.get(values.len()).ok_or_else(|| { self.error(format!("more values than the {} struct fields", fields.len())) })?;
+                        values.push((field.name.clone(), self.value(&field.ty)?));
+                        if !self.eat(',') {
+                            break;
+                        }
+                    }
+                    self.expect('}')?;
+                }
+                for field in &fields[values.len()..] {
+                    values.push((field.name.clone(), CompositeValue::default_of(&field.ty)?));
+                }
+                Ok(CompositeValue::Struct(values))
+            }
+            scalar => {
+                let text = self.scalar()?;
+                Self::scalar_value(scalar, &text).map_err(|error| { anyhow!(format!("incorrect {} value {:?}: {}", scalar, text, error)) })
+            }
+        }
+    }
+
+    fn scalar_value(ty: &CellType, text: &String) -> anyhow::Result<CompositeValue> {
+        let value = match ty {
+            CellType::UInt => CompositeValue::UInt(Parse::<u32>::parse(text)?),
+            CellType::Int => CompositeValue::Int(Parse::<i32>::parse(text)?),
+            CellType::Long => CompositeValue::Long(Parse::<i64>::parse(text)?),
+            CellType::Float => CompositeValue::Float(Parse::<f32>::parse(text)?),
+            CellType::Double => CompositeValue::Double(Parse::<f64>::parse(text)?),
+            CellType::Bool => CompositeValue::Bool(Parse::<bool>::parse(text)?),
+            CellType::String | CellType::Lang => CompositeValue::String(text.clone()),
+            other => return Err(anyhow!(format!("{} cannot be nested in array or struct", other))),
+        };
+        Ok(value)
+    }
+
+    /// a quoted string, or the text up to the next separator with surrounding whitespace trimmed
+    fn scalar(&mut self) -> anyhow::Result<String> {
+        self.skip_whitespace();
+        let mut text = String::new();
+        if self.eat('"') {
+            loop {
+                match self.next() {
+                    None => return Err(self.error("unterminated string")),
+                    Some('"') => return Ok(text),
+                    Some('\\') => text.push(self.next().ok_or_else(|| { self.error("nothing to escape") })?),
+                    Some(c) => text.push(c),
+                }
+            }
+        }
+        // escaped characters are kept even if they are whitespace at the end
+        let mut kept = 0;
+        while let Some(c) = self.chars.get(self.pos).copied() {
+            match c {
+                ',' | ']' | '}' => break,
+                '[' | '{' | '"' => return Err(self.error(format!("unexpected {}, escape it with \\", c))),
+                '\\' => {
+                    self.pos += 1;
+                    text.push(self.next().ok_or_else(|| { self.error("nothing to escape") })?);
+                    kept = text.len();
+                }
+                c => {
+                    self.pos += 1;
+                    text.push(c);
+                }
+            }
+        }
+        let trimmed = text.trim_end().len().max(kept);
+        text.truncate(trimmed);
+        Ok(text)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> anyhow::Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expect {}", c)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: impl ToString) -> anyhow::Error {
+        let rest = self.chars[self.pos..].iter().collect::<String>();
+        if rest.is_empty() {
+            anyhow!(format!("{} at the end", message.to_string()))
+        } else {
+            anyhow!(format!("{} at {:?}", message.to_string(), rest))
+        }
+    }
+}
+
+/// arrays become sequences and structs maps keyed by field name
+impl serde::Serialize for CompositeValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        match self {
+            CompositeValue::UInt(value) => value.serialize(serializer),
+            CompositeValue::Int(value) => value.serialize(serializer),
+            CompositeValue::Long(value) => value.serialize(serializer),
+            CompositeValue::Float(value) => value.serialize(serializer),
+            CompositeValue::Double(value) => value.serialize(serializer),
+            CompositeValue::Bool(value) => value.serialize(serializer),
+            CompositeValue::String(value) => value.serialize(serializer),
+            CompositeValue::Array(values) => values.serialize(serializer),
+            CompositeValue::Struct(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl ToLua for CompositeValue {
+    type Output = String;
+
+    fn to_lua(&self) -> anyhow::Result<Self::Output> {
+        match self {
+            CompositeValue::UInt(value) => value.to_lua(),
+            CompositeValue::Int(value) => value.to_lua(),
+            CompositeValue::Long(value) => value.to_lua(),
+            CompositeValue::Float(value) => value.to_lua(),
+            CompositeValue::Double(value) => value.to_lua(),
+            CompositeValue::Bool(value) => value.to_lua(),
+            CompositeValue::String(value) => value.to_lua(),
+            CompositeValue::Array(values) => {
+                let values = values.iter().map(|value| { value.to_lua() }).collect::<anyhow::Result<Vec<_>>>()?;
+                Ok(format!("{{ {} }}", values.join(", ")))
+            }
+            CompositeValue::Struct(fields) => {
+                let mut formatted_fields = vec![];
+                for (name, value) in fields {
+                    formatted_fields.push(format!("[{}] = {}", name.to_lua()?, value.to_lua()?));
+                }
+                Ok(format!("{{ {} }}", formatted_fields.join(", ")))
+            }
+        }
+    }
+}
+
+/// a composite cell deserialized into a type generated by `RustWriter` or written by hand
+pub fn parse_composite<T: serde::de::DeserializeOwned>(ty: &CellType, data: &str) -> anyhow::Result<T> {
+    let json = serde_json::to_string(&CompositeValue::parse(ty, data)?)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::excel::composite::{CompositeValue, parse_composite};
+    use crate::excel::convert::ToLua;
+    use crate::excel::excel_define::CellType;
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Reward {
+        id: u32,
+        count: i32,
+        name: String,
+    }
+
+    #[test]
+    fn test_composite() -> anyhow::Result<()> {
+        let rewards = CellType::parse_type("array<struct{id:uint,count:int,name:string}>")?;
+        let value = CompositeValue::parse(&rewards, r#"{1001, 2, "a,b"}, {1002, -1, c\}d\ }, {1003}"#)?;
+        assert_eq!(value.to_lua()?, r#"{ { ["id"] = 1001, ["count"] = 2, ["name"] = "a,b" }, { ["id"] = 1002, ["count"] = -1, ["name"] = "c}d " }, { ["id"] = 1003, ["count"] = 0, ["name"] = "" } }"#);
+        assert_eq!(serde_json::to_string(&value)?, r#"[{"id":1001,"count":2,"name":"a,b"},{"id":1002,"count":-1,"name":"c}d "},{"id":1003,"count":0,"name":""}]"#);
+        let parsed: Vec<Reward> = parse_composite(&rewards, "[{1001,2,x}]")?;
+        assert_eq!(parsed, vec![Reward { id: 1001, count: 2, name: "x".to_string() }]);
+        assert_eq!(CompositeValue::parse(&rewards, "")?, CompositeValue::Array(vec![]));
+
+        let matrix = CellType::parse_type("array<array<int>>")?;
+        assert_eq!(CompositeValue::parse(&matrix, "[1,2],[],[3]")?.to_lua()?, "{ { 1, 2 }, {  }, { 3 } }");
+        assert_eq!(CompositeValue::parse(&matrix, "[[1,2]]")?.to_lua()?, "{ { 1, 2 } }");
+        assert_eq!(CompositeValue::parse(&CellType::parse_type("array<float>")?, "0.5, 1")?.to_lua()?, "{ 0.5, 1 }");
+
+        assert_eq!(CompositeValue::parse(&rewards, "{1,2,3,4}").unwrap_err().to_string(), r#"more values than the 3 struct fields at "4}]""#);
+        assert!(CompositeValue::parse(&rewards, "{-1}").is_err());
+        assert_eq!(CompositeValue::parse(&CellType::parse_type("struct{on:bool,rate:float}")?, "1,")?.to_lua()?, r#"{ ["on"] = true, ["rate"] = 0 }"#);
+        assert!(CompositeValue::parse(&rewards, r#"{1,2,"x}"#).is_err());
+        assert!(CompositeValue::parse(&matrix, "[1,2").is_err());
+
+        let texts = CellType::parse_type("array<struct{id:uint,name:lang,tags:array<lang>}>")?;
+        let mut value = CompositeValue::parse(&texts, r#"{1,"a,\"b\"",[x,y]},{2}"#)?;
+        let paths = value.lang_texts(&texts).into_iter().map(|(path, text)| { format!("{}={}", path, text) }).collect::<Vec<_>>();
+        assert_eq!(paths, vec![r#"0.name=a,"b""#, "0.tags.0=x", "0.tags.1=y", "1.name="]);
+        assert_eq!(CompositeValue::parse(&texts, &value.to_cell())?, value);
+        Ok(())
+    }
+}
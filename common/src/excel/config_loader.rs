@@ -1,9 +1,287 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context};
+use tracing::{info, warn};
+
+use crate::excel::bundle::decode_bundle;
+use crate::excel::composite::parse_composite;
+use crate::excel::convert::*;
+use crate::excel::excel_define::{CellType, GameConfig, GameConfigs};
+
+/// rust types that a cell can be read as, bound to the `CellType`s they can represent
+pub trait CellValue: Sized {
+    fn accept(ty: &CellType) -> bool;
+}
+
+macro_rules! cell_value {
+    ($ty:ty, $($cell_type:ident),+) => {
+        impl CellValue for $ty {
+            fn accept(ty: &CellType) -> bool {
+                matches!(ty, $(CellType::$cell_type)|+)
+            }
+        }
+    };
+}
+
+cell_value!(u32, UInt);
+// enum and flags cells are exported as the integer values of their members
+impl CellValue for i32 {
+    fn accept(ty: &CellType) -> bool {
+        matches!(ty, CellType::Int | CellType::Enum(_) | CellType::Flags(_))
+    }
+}
+cell_value!(i64, Long);
+cell_value!(String, String, Lang);
+cell_value!(bool, Bool);
+cell_value!(DateTime, DateTime);
+cell_value!(Duration, Duration);
+cell_value!(CronSchedule, Cron);
+cell_value!(Vector3ArrayInt, Vector3ArrayInt);
+cell_value!(Vector3Int, Vector3Int);
+cell_value!(Vector2Int, Vector2Int);
+cell_value!(Vector3UInt, Vector3UInt);
+cell_value!(Vector2UInt, Vector2UInt);
+cell_value!(Vector2ArrayInt, Vector2ArrayInt);
+cell_value!(ArrayInt, ArrayInt);
+cell_value!(ArrayUInt, ArrayUInt);
+cell_value!(DictionaryStringFloat, DictionaryStringFloat);
+cell_value!(DictionaryStringInt, DictionaryStringInt);
+cell_value!(f32, Float);
+cell_value!(f64, Double);
+cell_value!(Vector2Float, Vector2Float);
+cell_value!(Vector3Float, Vector3Float);
+cell_value!(Vector2String, Vector2String);
+
+#[derive(typed_builder::TypedBuilder, Debug)]
+pub struct ConfigLoader {
+    #[builder(setter(into))]
+    pub path: PathBuf,
+    /// reject the bundle if it was not exported from this commit
+    #[builder(default, setter(strip_option, into))]
+    pub expect_commit_id: Option<String>,
+}
+
+impl ConfigLoader {
+    pub fn load(&self) -> anyhow::Result<ConfigTables> {
+        let game_configs = read_game_configs(&self.path)?;
+        self.validate(&game_configs)?;
+        let tables = ConfigTables::new(game_configs)?;
+        info!("load {} config tables from: {}, commit_id: {}, digest: {}, create_mills: {}", tables.tables.len(), self.path.display(), tables.commit_id, tables.digest, tables.create_mills);
+        Ok(tables)
+    }
+
+    fn validate(&self, game_configs: &GameConfigs) -> anyhow::Result<()> {
+        if let Some(expect_commit_id) = &self.expect_commit_id {
+            if *expect_commit_id != game_configs.commit_id {
+                return Err(anyhow!(format!("config commit_id mismatch, expect: {}, got: {}", expect_commit_id, game_configs.commit_id)));
+            }
+        }
+        if !game_configs.digest.is_empty() {
+            let digest = game_configs.compute_digest()?;
+            if digest != game_configs.digest {
+                return Err(anyhow!(format!("config digest mismatch, expect: {}, got: {}", game_configs.digest, digest)));
+            }
+        }
+        if game_configs.create_mills == 0 {
+            return Err(anyhow!("config create_mills is not set"));
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        if game_configs.create_mills > now {
+            warn!("config create_mills {} is later than now {}", game_configs.create_mills, now);
+        }
+        Ok(())
+    }
+}
+
+/// read the `GameConfigs` bundle written by `excel_tool --bytes`, see `bundle`
+pub fn read_game_configs<P: AsRef<Path>>(path: P) -> anyhow::Result<GameConfigs> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).context(format!("failed to open file: {}", path.display()))?;
+    decode_bundle(&bytes).context(format!("failed to read: {}", path.display()))
+}
+
+#[derive(Debug)]
+pub struct ConfigTables {
+    pub commit_id: String,
+    pub digest: String,
+    pub create_mills: u128,
+    pub tables: HashMap<String, ConfigTable>,
+}
+
+impl ConfigTables {
+    pub fn new(game_configs: GameConfigs) -> anyhow::Result<Self> {
+        let mut tables = HashMap::with_capacity(game_configs.data.len());
+        for config in game_configs.data {
+            let name = config.name.clone();
+            let table = ConfigTable::new(config)?;
+            if tables.insert(name.clone(), table).is_some() {
+                return Err(anyhow!(format!("duplicate config name: {}", name)));
+            }
+        }
+        Ok(Self {
+            commit_id: game_configs.commit_id,
+            digest: game_configs.digest,
+            create_mills: game_configs.create_mills,
+            tables,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConfigTable> {
+        self.tables.get(name)
+    }
+
+    pub fn table(&self, name: &str) -> anyhow::Result<&ConfigTable> {
+        self.get(name).ok_or(anyhow!(format!("config {} not found", name)))
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigTable {
+    config: GameConfig,
+    key_indexes: Vec<usize>,
+    column_index: HashMap<String, usize>,
+    key_to_row: HashMap<String, usize>,
+}
+
+impl ConfigTable {
+    pub fn new(config: GameConfig) -> anyhow::Result<Self> {
+        let columns = config.cell_name.len();
+        if config.cell_type.len() != columns || config.key_type.len() != columns {
+            return Err(anyhow!(format!("{} header length mismatch, cell_name: {}, cell_type: {}, key_type: {}", config.name, columns, config.cell_type.len(), config.key_type.len())));
+        }
+        let key_indexes = config.side_key_indexes();
+        if key_indexes.is_empty() {
+            return Err(anyhow!(format!("{} {} key not found", config.name, config.side)));
+        }
+        let mut column_index = HashMap::with_capacity(columns);
+        for (i, name) in config.cell_name.iter().enumerate() {
+            column_index.insert(name.clone(), i);
+        }
+        let mut key_to_row = HashMap::with_capacity(config.data.len());
+        for (i, row) in config.data.iter().enumerate() {
+            if row.len() != columns {
+                return Err(anyhow!(format!("{} row {} expect {} cells, got: {}", config.name, i + 1, columns, row.len())));
+            }
+            let key = config.row_key(row, &key_indexes)?;
+            if let Some(first) = key_to_row.insert(key.clone(), i) {
+                return Err(anyhow!(format!("{} row {} duplicate key: {}, first defined at row {}", config.name, i + 1, key, first + 1)));
+            }
+        }
+        Ok(Self {
+            config,
+            key_indexes,
+            column_index,
+            key_to_row,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    pub fn key_names(&self) -> Vec<&str> {
+        self.key_indexes.iter().map(|i| { self.config.cell_name[*i].as_str() }).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.config.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.config.data.is_empty()
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.column_index.get(name).copied()
+    }
+
+    /// composite keys are joined by `COMPOSITE_KEY_SEPARATOR`
+    pub fn row(&self, key: impl ToString) -> Option<ConfigRow<'_>> {
+        let index = *self.key_to_row.get(&key.to_string())?;
+        Some(ConfigRow { table: self, index })
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item=ConfigRow<'_>> {
+        (0..self.config.data.len()).map(move |index| { ConfigRow { table: self, index } })
+    }
+
+    pub fn get<T>(&self, key: impl ToString, column: &str) -> anyhow::Result<T> where T: CellValue, String: Parse<T> {
+        let key = key.to_string();
+        let row = self.row(&key).ok_or(anyhow!(format!("{} key {} not found", self.name(), key)))?;
+        row.get(column)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigRow<'a> {
+    table: &'a ConfigTable,
+    index: usize,
+}
+
+impl<'a> ConfigRow<'a> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn key(&self) -> String {
+        let config = &self.table.config;
+        config.row_key(&config.data[self.index], &self.table.key_indexes).unwrap_or_default()
+    }
+
+    pub fn raw(&self, column: usize) -> &'a str {
+        &self.table.config.data[self.index][column]
+    }
+
+    pub fn get<T>(&self, column: &str) -> anyhow::Result<T> where T: CellValue, String: Parse<T> {
+        let config = &self.table.config;
+        let i = self.table.column(column).ok_or(anyhow!(format!("{} column {} not found", config.name, column)))?;
+        let ty = &config.cell_type[i];
+        if !T::accept(ty) {
+            return Err(anyhow!(format!("{} column {} is {}, cannot read as {}", config.name, column, ty, std::any::type_name::<T>())));
+        }
+        let data = &config.data[self.index][i];
+        data.parse().context(format!("{} key {} column {} incorrect cell data: {}", config.name, self.key(), column, data))
+    }
+
+    /// `None` for the empty cells of a nullable column
+    pub fn get_option<T>(&self, column: &str) -> anyhow::Result<Option<T>> where T: CellValue, String: Parse<T> {
+        let config = &self.table.config;
+        let i = self.table.column(column).ok_or(anyhow!(format!("{} column {} not found", config.name, column)))?;
+        if config.is_null(self.index, i) {
+            return Ok(None);
+        }
+        self.get(column).map(Some)
+    }
+
+    /// an array or struct cell deserialized by its field names
+    pub fn get_composite<T: serde::de::DeserializeOwned>(&self, column: &str) -> anyhow::Result<T> {
+        let config = &self.table.config;
+        let i = self.table.column(column).ok_or(anyhow!(format!("{} column {} not found", config.name, column)))?;
+        let ty = &config.cell_type[i];
+        if !ty.is_composite() {
+            return Err(anyhow!(format!("{} column {} is {}, cannot read as {}", config.name, column, ty, std::any::type_name::<T>())));
+        }
+        let data = &config.data[self.index][i];
+        parse_composite(ty, data).context(format!("{} key {} column {} incorrect cell data: {}", config.name, self.key(), column, data))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::env;
 
     use anyhow::Context;
 
+    use crate::excel::bundle::write_bundle;
+    use crate::excel::config_loader::ConfigLoader;
+    use crate::excel::convert::Vector2Int;
+    use crate::excel::excel_define::{CellType, GameConfig, GameConfigs, KeyType};
     use crate::init_logger;
     use crate::lua_helper::RustUtil;
 
@@ -19,4 +297,32 @@ mod test {
         lua.load(&init).exec()?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn load_bytes() -> anyhow::Result<()> {
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "name".to_string(), "pos".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::Server])
+            .cell_type(vec![CellType::UInt, CellType::String, CellType::Vector2Int])
+            .data(vec![vec!["1001".to_string(), "sword".to_string(), "1,2".to_string()]])
+            .build();
+        let mut game_configs = GameConfigs::builder().commit_id("abc".to_string()).create_mills(1).data(vec![config]).build();
+        game_configs.digest = game_configs.compute_digest()?;
+        let path = env::temp_dir().join("load_bytes_config.bytes");
+        write_bundle(&game_configs, 4, &path)?;
+
+        let tables = ConfigLoader::builder().path(&path).expect_commit_id("abc").build().load()?;
+        assert_eq!(tables.digest, game_configs.digest);
+        let item = tables.table("item")?;
+        assert_eq!(item.key_names(), vec!["id"]);
+        assert_eq!(item.get::<String>(1001, "name")?, "sword");
+        let row = item.row(1001).unwrap();
+        assert_eq!(row.get::<u32>("id")?, 1001);
+        assert_eq!(row.get::<Vector2Int>("pos")?, (1, 2));
+        assert!(row.get::<i32>("id").is_err());
+        assert!(item.row(1002).is_none());
+        assert!(ConfigLoader::builder().path(&path).expect_commit_id("def").build().load().is_err());
+        Ok(())
+    }
+}
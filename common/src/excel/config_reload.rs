@@ -0,0 +1,270 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Context};
+use tracing::{error, info};
+
+use crate::excel::checker::check_exported_config;
+use crate::excel::config_loader::{ConfigLoader, ConfigTables};
+use crate::lua_helper::reload_lua_configs;
+
+/// holds the active config set and swaps it only after a new one is fully loaded and checked
+pub struct ConfigReloader {
+    loader: ConfigLoader,
+    /// generated lua of the same export as the bundle, passed to `ReloadGameConfigs`, its default dir if not given
+    lua_dir: Option<String>,
+    current: RwLock<Arc<ConfigTables>>,
+}
+
+impl ConfigReloader {
+    pub fn new(loader: ConfigLoader, lua_dir: Option<String>) -> anyhow::Result<Self> {
+        let tables = Self::load(&loader)?;
+        Ok(Self {
+            loader,
+            lua_dir,
+            current: RwLock::new(Arc::new(tables)),
+        })
+    }
+
+    pub fn current(&self) -> Arc<ConfigTables> {
+        self.current.read().expect("config lock poisoned").clone()
+    }
+
+    /// load a new config set, then reload the `GameConfigs` of the running `lua` state if given,
+    /// the current set is kept if anything fails and the lua side restores its previous configs itself
+    pub fn reload(&self, lua: Option<&mlua::Lua>) -> anyhow::Result<Arc<ConfigTables>> {
+        let result = Self::load(&self.loader).and_then(|tables| {
+            if let Some(lua) = lua {
+                reload_lua_configs(lua, self.lua_dir.as_deref())?;
+            }
+            Ok(tables)
+        });
+        match result {
+            Ok(tables) => {
+                let tables = Arc::new(tables);
+                let previous = std::mem::replace(&mut *self.current.write().expect("config lock poisoned"), tables.clone());
+                info!("config reloaded, commit_id: {} -> {}, digest: {} -> {}, create_mills: {} -> {}", previous.commit_id, tables.commit_id, previous.digest, tables.digest, previous.create_mills, tables.create_mills);
+                Ok(tables)
+            }
+            Err(error) => {
+                let current = self.current();
+                error!("config reload failed, keep commit_id: {}, digest: {}, create_mills: {}, error: {:?}", current.commit_id, current.digest, current.create_mills, error);
+                Err(error)
+            }
+        }
+    }
+
+    fn load(loader: &ConfigLoader) -> anyhow::Result<ConfigTables> {
+        let tables = loader.load()?;
+        let mut errors = vec![];
+        for table in tables.tables.values() {
+            errors.extend(check_exported_config(table.config()));
+        }
+        if !errors.is_empty() {
+            for error in &errors {
+                error!("{}", error);
+            }
+            return Err(anyhow!(format!("config data check failed with {} errors", errors.len())));
+        }
+        Ok(tables)
+    }
+}
+
+/// poll the watched paths and call `on_change` when they changed and stayed unchanged for one interval
+///
+/// lua states cannot leave their thread, so the owner of the lua state calls `ConfigReloader::reload` on `on_change`
+pub struct ConfigWatcher {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ConfigWatcher {
+    pub fn spawn<F>(paths: Vec<PathBuf>, interval: Duration, on_change: F) -> anyhow::Result<Self> where F: Fn() + Send + 'static {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut last = fingerprint(&paths)?;
+        let thread_stop = stop.clone();
+        let handle = std::thread::Builder::new().name("config_watcher".to_string()).spawn(move || {
+            let mut pending: Option<Vec<(PathBuf, SystemTime, u64)>> = None;
+            while !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(interval);
+                let current = match fingerprint(&paths) {
+                    Ok(current) => current,
+                    Err(error) => {
+                        error!("failed to scan config files: {:?}", error);
+                        continue;
+                    }
+                };
+                if current == last {
+                    pending = None;
+                    continue;
+                }
+                if pending.as_ref() != Some(&current) {
+                    // files are still being written, wait until they are stable
+                    pending = Some(current);
+                    continue;
+                }
+                info!("config files changed");
+                last = current;
+                pending = None;
+                on_change();
+            }
+        }).context("failed to spawn config watcher")?;
+        Ok(Self { stop, handle: Some(handle) })
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                error!("config watcher panicked");
+            }
+        }
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn fingerprint(paths: &[PathBuf]) -> anyhow::Result<Vec<(PathBuf, SystemTime, u64)>> {
+    let mut files = vec![];
+    for path in paths {
+        if !path.exists() {
+            continue;
+        }
+        for dir in walkdir::WalkDir::new(path) {
+            let dir = dir?;
+            if dir.file_type().is_file() {
+                let metadata = dir.metadata()?;
+                files.push((dir.into_path(), metadata.modified()?, metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::path::Path;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use chrono::FixedOffset;
+
+    use crate::excel::bundle::write_bundle;
+    use crate::excel::config_loader::ConfigLoader;
+    use crate::excel::config_reload::{ConfigReloader, ConfigWatcher};
+    use crate::excel::excel_define::{CellRule, CellType, GameConfig, GameConfigs, KeyType};
+    use crate::excel::export::{ExportOptions, prepare_configs};
+    use crate::excel::report::ErrorReport;
+    use crate::lua_helper::new_lua_state;
+
+    fn write_bytes(path: &Path, commit_id: &str, level: &str) -> anyhow::Result<()> {
+        let config = GameConfig::builder()
+            .name("level".to_string())
+            .cell_name(vec!["id".to_string(), "exp".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::UInt])
+            .data(vec![vec!["1".to_string(), level.to_string()]])
+            .build();
+        let game_configs = GameConfigs::builder().commit_id(commit_id.to_string()).create_mills(1).data(vec![config]).build();
+        write_bundle(&game_configs, 4, path)
+    }
+
+    #[test]
+    fn test_reload() -> anyhow::Result<()> {
+        let path = env::temp_dir().join("test_reload_config.bytes");
+        write_bytes(&path, "a", "100")?;
+        let reloader = ConfigReloader::new(ConfigLoader::builder().path(&path).build(), None)?;
+        assert_eq!(reloader.current().commit_id, "a");
+
+        write_bytes(&path, "b", "-1")?;
+        assert!(reloader.reload(None).is_err());
+        assert_eq!(reloader.current().commit_id, "a");
+
+        write_bytes(&path, "c", "200")?;
+        reloader.reload(None)?;
+        let current = reloader.current();
+        assert_eq!(current.commit_id, "c");
+        assert_eq!(current.table("level")?.get::<u32>(1, "exp")?, 200);
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch() -> anyhow::Result<()> {
+        let path = env::temp_dir().join("test_watch_config.bytes");
+        write_bytes(&path, "a", "100")?;
+        let reloader = ConfigReloader::new(ConfigLoader::builder().path(&path).build(), None)?;
+        let (sender, receiver) = channel();
+        let mut watcher = ConfigWatcher::spawn(vec![path.clone()], Duration::from_millis(10), move || {
+            let _ = sender.send(());
+        })?;
+        write_bytes(&path, "b", "2000")?;
+        receiver.recv_timeout(Duration::from_secs(5))?;
+        assert_eq!(reloader.reload(None)?.commit_id, "b");
+        watcher.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_lua() -> anyhow::Result<()> {
+        let path = env::temp_dir().join("test_reload_lua_config.bytes");
+        write_bytes(&path, "a", "100")?;
+        let reloader = ConfigReloader::new(ConfigLoader::builder().path(&path).build(), Some("lua/generated_excel".to_string()))?;
+        let lua = new_lua_state()?;
+        lua.load(r#"
+            function ReloadGameConfigs(dir)
+                ReloadedDir = dir
+                if Reject then
+                    return false, "hook failed"
+                end
+                return true, nil
+            end
+            Reject = true
+        "#).exec()?;
+        write_bytes(&path, "b", "200")?;
+        assert!(reloader.reload(Some(&lua)).is_err());
+        assert_eq!(reloader.current().commit_id, "a");
+
+        lua.globals().set("Reject", false)?;
+        assert_eq!(reloader.reload(Some(&lua))?.commit_id, "b");
+        assert_eq!(lua.globals().get::<_, String>("ReloadedDir")?, "lua/generated_excel");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_resolved_cells() -> anyhow::Result<()> {
+        let path = env::temp_dir().join("test_reload_resolved_cells.bytes");
+        let event = GameConfig::builder()
+            .name("event".to_string())
+            .cell_name(vec!["id".to_string(), "start".to_string(), "cooldown".to_string(), "title".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::DateTime, CellType::Duration, CellType::Lang])
+            .cell_rule(vec![
+                vec![],
+                CellRule::parse_rules(r"regex:^\d{4}-\d{2}-\d{2}")?,
+                CellRule::parse_rules("enum:3600,7200")?,
+                CellRule::parse_rules("regex:^Spring")?,
+            ])
+            .data(vec![vec!["1".to_string(), "2024-03-01 10:00:00".to_string(), "1h".to_string(), "Spring Festival".to_string()]])
+            .build();
+        let mut game_configs = GameConfigs::builder().commit_id("a".to_string()).create_mills(1).data(vec![event]).build();
+        let options = ExportOptions::builder().timezone(FixedOffset::east_opt(0).unwrap()).build();
+        let mut report = ErrorReport::default();
+        prepare_configs(&mut game_configs, &options, &mut report)?;
+        assert!(report.is_empty());
+        write_bundle(&game_configs, 4, &path)?;
+        // the rules were written for the workbook text, the bundle has seconds and lang keys
+        let reloader = ConfigReloader::new(ConfigLoader::builder().path(&path).build(), None)?;
+        assert_eq!(reloader.current().table("event")?.len(), 1);
+        assert_eq!(reloader.reload(None)?.commit_id, "a");
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
+use crate::excel::composite::CompositeValue;
 use crate::excel::excel_define::CellType;
+use crate::excel::lua_emitter::{lua_float, lua_string};
+use crate::excel::report::{CellLocation, ExcelError};
 
 pub type Vector3ArrayInt = Vec<(i32, i32, i32)>;
 pub type Vector3Int = (i32, i32, i32);
@@ -16,6 +19,10 @@ pub type Lang = String;
 pub type Vector2Float = (f32, f32);
 pub type Vector3Float = (f32, f32, f32);
 pub type Vector2String = (String, String);
+pub type DateTime = chrono::DateTime<chrono::Utc>;
+pub type Duration = std::time::Duration;
+// generated code only imports this module
+pub use crate::excel::time_define::CronSchedule;
 
 pub trait ToLua {
     type Output;
@@ -41,18 +48,31 @@ macro_rules! to_lua {
 to_lua!(u32);
 to_lua!(i32);
 to_lua!(i64);
+to_lua!(u64);
 // to_lua!(String);
 impl ToLua for String {
     type Output = String;
 
     fn to_lua(&self) -> anyhow::Result<Self::Output> {
-        let escape_string = self.replace("\"", "\\\"").replace("\r", "\\\r").replace("\n", "\\\n");
-        Ok(format!("\"{}\"", escape_string))
+        Ok(lua_string(self))
     }
 }
 to_lua!(bool);
-to_lua!(f32);
-to_lua!(f64);
+
+macro_rules! float_to_lua {
+    ($ty:ty) => {
+        impl ToLua for $ty {
+            type Output = String;
+
+            fn to_lua(&self) -> anyhow::Result<Self::Output> {
+                Ok(lua_float(*self))
+            }
+        }
+    };
+}
+
+float_to_lua!(f32);
+float_to_lua!(f64);
 
 macro_rules! array_to_lua {
     ($name:ident) => {
@@ -60,7 +80,7 @@ macro_rules! array_to_lua {
             type Output = String;
 
             fn to_lua(&self) -> anyhow::Result<Self::Output> {
-                let string_vec = self.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+                let string_vec = self.iter().map(|value| { value.to_lua() }).collect::<anyhow::Result<Vec<_>>>()?.join(", ");
                 Ok(format!("{{ {} }}", string_vec))
             }
         }
@@ -129,9 +149,11 @@ macro_rules! map_to_lua {
             type Output = String;
 
             fn to_lua(&self) -> anyhow::Result<Self::Output> {
+                let mut keys = self.keys().collect::<Vec<_>>();
+                keys.sort();
                 let mut kv = vec![];
-                for (k, v) in self {
-                    kv.push(format!("{{{} = {}}}", k, v));
+                for k in keys {
+                    kv.push(format!("[{}] = {}", k.to_lua()?, self[k].to_lua()?));
                 }
                 Ok(format!("{{ {} }}", kv.join(", ")))
             }
@@ -144,7 +166,7 @@ map_to_lua!(DictionaryStringInt);
 
 macro_rules! default {
     ($str:expr) => {
-        if $str.is_empty() || $str == "0" {
+        if $str.is_empty() {
             return Ok(Default::default());
         }
     };
@@ -182,6 +204,29 @@ impl Parse<bool> for String {
 }
 parse!(String);
 
+impl Parse<DateTime> for String {
+    fn parse(&self) -> anyhow::Result<DateTime> {
+        default!(self);
+        crate::excel::time_define::datetime_of(self)
+    }
+}
+
+impl Parse<Duration> for String {
+    fn parse(&self) -> anyhow::Result<Duration> {
+        default!(self);
+        Ok(Duration::from_secs(crate::excel::time_define::parse_duration(self)?))
+    }
+}
+
+impl Parse<CronSchedule> for String {
+    fn parse(&self) -> anyhow::Result<CronSchedule> {
+        if self.is_empty() {
+            return Ok(Default::default());
+        }
+        CronSchedule::parse(self)
+    }
+}
+
 macro_rules! parse_vector2 {
     ($ty:ty) => {
         impl Parse<$ty> for String {
@@ -261,9 +306,43 @@ macro_rules! parse_vector_array {
 parse_vector_array!(Vector2ArrayInt);
 parse_vector_array!(Vector3ArrayInt);
 
+/// dictionary cell syntax: `key:value;key:value`, e.g. `atk:1.5;def:2`
+macro_rules! parse_map {
+    ($ty:ty) => {
+        impl Parse<$ty> for String {
+            fn parse(&self) -> anyhow::Result<$ty> {
+                default!(self);
+                let mut map = <$ty>::new();
+                for entry in self.split(";") {
+                    if entry.trim().is_empty() {
+                        continue;
+                    }
+                    let kv = entry.split(":").map(|t| { t.trim().to_string() }).collect::<Vec<String>>();
+                    if kv.len() != 2 || kv[0].is_empty() {
+                        return Err(anyhow::anyhow!(format!("incorrect dictionary entry: {}, expect key:value in cell data: {}", entry, self)));
+                    }
+                    let value = kv[1].parse().map_err(|e| { anyhow::anyhow!(format!("incorrect dictionary value of key {}: {} in cell data: {}", kv[0], e, self)) })?;
+                    if map.insert(kv[0].clone(), value).is_some() {
+                        return Err(anyhow::anyhow!(format!("duplicate dictionary key: {} in cell data: {}", kv[0], self)));
+                    }
+                }
+                Ok(map)
+            }
+        }
+    };
+}
+
+parse_map!(DictionaryStringFloat);
+parse_map!(DictionaryStringInt);
+
 pub struct LuaWriter;
 
 impl LuaWriter {
+    /// same as `write` with the cell location attached to the error
+    pub fn write_at(ty: &CellType, data: &String, location: &CellLocation) -> anyhow::Result<String> {
+        Self::write(ty, data).map_err(|error| { ExcelError::at(location, data, error).into() })
+    }
+
     pub fn write(ty: &CellType, data: &String) -> anyhow::Result<String> {
         match ty {
             CellType::UInt => {
@@ -306,10 +385,10 @@ impl LuaWriter {
                 crate::parse!(data,ArrayUInt).to_lua()
             }
             CellType::DictionaryStringFloat => {
-                unimplemented!("DictionaryStringFloat")
+                crate::parse!(data,DictionaryStringFloat).to_lua()
             }
             CellType::DictionaryStringInt => {
-                unimplemented!("DictionaryStringInt")
+                crate::parse!(data,DictionaryStringInt).to_lua()
             }
             CellType::Lang => {
                 crate::parse!(data,String).to_lua()
@@ -329,6 +408,150 @@ impl LuaWriter {
             CellType::Vector2String => {
                 crate::parse!(data,Vector2String).to_lua()
             }
+            CellType::Enum(_) | CellType::Flags(_) => {
+                crate::parse!(data,i32).to_lua()
+            }
+            CellType::Array(_) | CellType::Struct(_) => {
+                CompositeValue::parse(ty, data)?.to_lua()
+            }
+            CellType::DateTime => {
+                crate::parse!(data,DateTime).timestamp().to_lua()
+            }
+            CellType::Duration => {
+                crate::parse!(data,Duration).as_secs().to_lua()
+            }
+            CellType::Cron => {
+                crate::parse!(data,CronSchedule).expression().to_string().to_lua()
+            }
         }
     }
+}
+
+/// typed json value of a cell, vectors become arrays and dictionaries become objects
+pub struct JsonWriter;
+
+impl JsonWriter {
+    pub fn write_at(ty: &CellType, data: &String, location: &CellLocation) -> anyhow::Result<serde_json::Value> {
+        Self::write(ty, data).map_err(|error| { ExcelError::at(location, data, error).into() })
+    }
+
+    pub fn write(ty: &CellType, data: &String) -> anyhow::Result<serde_json::Value> {
+        match ty {
+            CellType::UInt => {
+                to_json(&crate::parse!(data,u32))
+            }
+            CellType::Int => {
+                to_json(&crate::parse!(data,i32))
+            }
+            CellType::Long => {
+                to_json(&crate::parse!(data,i64))
+            }
+            CellType::String => {
+                to_json(&crate::parse!(data,String))
+            }
+            CellType::Bool => {
+                to_json(&crate::parse!(data,bool))
+            }
+            CellType::Vector3ArrayInt => {
+                to_json(&crate::parse!(data,Vector3ArrayInt))
+            }
+            CellType::Vector3Int => {
+                to_json(&crate::parse!(data,Vector3Int))
+            }
+            CellType::Vector2Int => {
+                to_json(&crate::parse!(data,Vector2Int))
+            }
+            CellType::Vector3UInt => {
+                to_json(&crate::parse!(data,Vector3UInt))
+            }
+            CellType::Vector2UInt => {
+                to_json(&crate::parse!(data,Vector2UInt))
+            }
+            CellType::Vector2ArrayInt => {
+                to_json(&crate::parse!(data,Vector2ArrayInt))
+            }
+            CellType::ArrayInt => {
+                to_json(&crate::parse!(data,ArrayInt))
+            }
+            CellType::ArrayUInt => {
+                to_json(&crate::parse!(data,ArrayUInt))
+            }
+            CellType::DictionaryStringFloat => {
+                to_json(&crate::parse!(data,DictionaryStringFloat))
+            }
+            CellType::DictionaryStringInt => {
+                to_json(&crate::parse!(data,DictionaryStringInt))
+            }
+            CellType::Lang => {
+                to_json(&crate::parse!(data,String))
+            }
+            CellType::Float => {
+                to_json(&crate::parse!(data,f32))
+            }
+            CellType::Double => {
+                to_json(&crate::parse!(data,f64))
+            }
+            CellType::Vector2Float => {
+                to_json(&crate::parse!(data,Vector2Float))
+            }
+            CellType::Vector3Float => {
+                to_json(&crate::parse!(data,Vector3Float))
+            }
+            CellType::Vector2String => {
+                to_json(&crate::parse!(data,Vector2String))
+            }
+            CellType::Enum(_) | CellType::Flags(_) => {
+                to_json(&crate::parse!(data,i32))
+            }
+            CellType::Array(_) | CellType::Struct(_) => {
+                to_json(&CompositeValue::parse(ty, data)?)
+            }
+            CellType::DateTime => {
+                to_json(&crate::parse!(data,DateTime).timestamp())
+            }
+            CellType::Duration => {
+                to_json(&crate::parse!(data,Duration).as_secs())
+            }
+            CellType::Cron => {
+                to_json(&crate::parse!(data,CronSchedule).expression())
+            }
+        }
+    }
+}
+
+/// round trip through json text, `serde_json::to_value` widens f32 to f64 and would write 0.1 as 0.10000000149011612
+fn to_json<T: serde::Serialize>(value: &T) -> anyhow::Result<serde_json::Value> {
+    Ok(serde_json::from_str(&serde_json::to_string(value)?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::excel::convert::{DictionaryStringFloat, DictionaryStringInt, JsonWriter, LuaWriter, Parse};
+    use crate::excel::excel_define::CellType;
+
+    #[test]
+    fn test_dictionary() -> anyhow::Result<()> {
+        let data = "atk:1.5;def:2".to_string();
+        let parsed: DictionaryStringFloat = data.parse()?;
+        assert_eq!(parsed.get("atk"), Some(&1.5));
+        assert_eq!(parsed.get("def"), Some(&2.0));
+        assert_eq!(LuaWriter::write(&CellType::DictionaryStringFloat, &data)?, r#"{ ["atk"] = 1.5, ["def"] = 2 }"#);
+        assert_eq!(LuaWriter::write(&CellType::DictionaryStringInt, &"def:2;atk:1;".to_string())?, r#"{ ["atk"] = 1, ["def"] = 2 }"#);
+        let empty: DictionaryStringInt = "".to_string().parse()?;
+        assert!(empty.is_empty());
+        assert!(Parse::<DictionaryStringInt>::parse(&"atk:1.5".to_string()).is_err());
+        assert!(Parse::<DictionaryStringInt>::parse(&"atk".to_string()).is_err());
+        assert!(Parse::<DictionaryStringInt>::parse(&"atk:1;atk:2".to_string()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json() -> anyhow::Result<()> {
+        assert_eq!(JsonWriter::write(&CellType::Float, &"0.1".to_string())?.to_string(), "0.1");
+        assert_eq!(JsonWriter::write(&CellType::Vector2ArrayInt, &"1,2;3,-4".to_string())?.to_string(), "[[1,2],[3,-4]]");
+        assert_eq!(JsonWriter::write(&CellType::DictionaryStringFloat, &"def:2;atk:1.5".to_string())?.to_string(), r#"{"atk":1.5,"def":2.0}"#);
+        assert_eq!(JsonWriter::write(&CellType::Bool, &"".to_string())?.to_string(), "false");
+        assert!(JsonWriter::write(&CellType::UInt, &"-1".to_string()).is_err());
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+
+use crate::excel::excel_define::{GameConfig, GameConfigs};
+
+/// changes between two exports, sheets and columns are matched by name and rows by key
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ConfigDiff {
+    pub added_sheets: Vec<String>,
+    pub removed_sheets: Vec<String>,
+    pub changed_sheets: Vec<SheetDiff>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct SheetDiff {
+    pub name: String,
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    pub changed_columns: Vec<ColumnChange>,
+    pub added_rows: Vec<String>,
+    pub removed_rows: Vec<String>,
+    pub changed_cells: Vec<CellChange>,
+}
+
+/// cell type or key type of a column, written as `uint allkey`
+#[derive(Debug, serde::Serialize)]
+pub struct ColumnChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CellChange {
+    pub row: String,
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl ConfigDiff {
+    pub fn diff(old: &GameConfigs, new: &GameConfigs) -> ConfigDiff {
+        let old_sheets = old.data.iter().map(|config| { (config.name.as_str(), config) }).collect::<BTreeMap<_, _>>();
+        let new_sheets = new.data.iter().map(|config| { (config.name.as_str(), config) }).collect::<BTreeMap<_, _>>();
+        let mut diff = ConfigDiff::default();
+        for (name, old_sheet) in &old_sheets {
+            match new_sheets.get(name) {
+                None => diff.removed_sheets.push(name.to_string()),
+                Some(new_sheet) => {
+                    let sheet_diff = SheetDiff::diff(old_sheet, new_sheet);
+                    if !sheet_diff.is_empty() {
+                        diff.changed_sheets.push(sheet_diff);
+                    }
+                }
+            }
+        }
+        diff.added_sheets = new_sheets.keys().filter(|name| { !old_sheets.contains_key(*name) }).map(ToString::to_string).collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_sheets.is_empty() && self.removed_sheets.is_empty() && self.changed_sheets.is_empty()
+    }
+}
+
+impl SheetDiff {
+    pub fn diff(old: &GameConfig, new: &GameConfig) -> SheetDiff {
+        let old_columns = column_indexes(old);
+        let new_columns = column_indexes(new);
+        let mut diff = SheetDiff { name: new.name.clone(), ..Default::default() };
+        diff.removed_columns = old.cell_name.iter().filter(|name| { !new_columns.contains_key(name.as_str()) }).cloned().collect();
+        diff.added_columns = new.cell_name.iter().filter(|name| { !old_columns.contains_key(name.as_str()) }).cloned().collect();
+        // columns in both sheets, in the order of the new sheet
+        let common_columns = new.cell_name.iter().filter_map(|name| { old_columns.get(name.as_str()).map(|old_index| { (name, *old_index, new_columns[name.as_str()]) }) }).collect::<Vec<_>>();
+        for (name, old_index, new_index) in &common_columns {
+            let (old_column, new_column) = (column_desc(old, *old_index), column_desc(new, *new_index));
+            if old_column != new_column {
+                diff.changed_columns.push(ColumnChange { column: name.to_string(), old: old_column, new: new_column });
+            }
+        }
+        let old_keys = row_keys(old);
+        let new_keys = row_keys(new);
+        let old_rows = first_rows(&old_keys);
+        let new_rows = first_rows(&new_keys);
+        diff.removed_rows = old_keys.iter().enumerate().filter(|(i, key)| { old_rows[key.as_str()] == *i && !new_rows.contains_key(key.as_str()) }).map(|(_, key)| { key.clone() }).collect();
+        for (i, key) in new_keys.iter().enumerate() {
+            if new_rows[key.as_str()] != i {
+                continue;
+            }
+            let old_row = match old_rows.get(key.as_str()) {
+                None => {
+                    diff.added_rows.push(key.clone());
+                    continue;
+                }
+                Some(old_row) => &old.data[*old_row],
+            };
+            for (name, old_index, new_index) in &common_columns {
+                let (old_data, new_data) = (&old_row[*old_index], &new.data[i][*new_index]);
+                if old_data != new_data {
+                    diff.changed_cells.push(CellChange { row: key.clone(), column: name.to_string(), old: old_data.clone(), new: new_data.clone() });
+                }
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_columns.is_empty() && self.removed_columns.is_empty() && self.changed_columns.is_empty()
+            && self.added_rows.is_empty() && self.removed_rows.is_empty() && self.changed_cells.is_empty()
+    }
+}
+
+fn column_indexes(config: &GameConfig) -> HashMap<&str, usize> {
+    config.cell_name.iter().enumerate().map(|(i, name)| { (name.as_str(), i) }).collect()
+}
+
+fn column_desc(config: &GameConfig, column: usize) -> String {
+    format!("{} {}", config.cell_type[column], config.key_type[column])
+}
+
+/// key of every row, excel row numbers like `#6` if the sheet has no key or a key cannot be parsed
+fn row_keys(config: &GameConfig) -> Vec<String> {
+    let key_indexes = config.lua_key_indexes().unwrap_or_default();
+    config.data.iter().enumerate().map(|(i, row)| {
+        if key_indexes.is_empty() {
+            return format!("#{}", config.row_number_of(i));
+        }
+        config.row_key(row, &key_indexes).unwrap_or_else(|_| { format!("#{}", config.row_number_of(i)) })
+    }).collect()
+}
+
+/// index of the first row of every key, rows with a duplicate key are reported by the key check of the export
+fn first_rows(keys: &[String]) -> HashMap<&str, usize> {
+    let mut rows = HashMap::with_capacity(keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        rows.entry(key.as_str()).or_insert(i);
+    }
+    rows
+}
+
+impl Display for ConfigDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for name in &self.added_sheets {
+            writeln!(f, "+ sheet {}", name)?;
+        }
+        for name in &self.removed_sheets {
+            writeln!(f, "- sheet {}", name)?;
+        }
+        for sheet in &self.changed_sheets {
+            write!(f, "{}", sheet)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SheetDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "~ sheet {}", self.name)?;
+        for column in &self.added_columns {
+            writeln!(f, "    + column {}", column)?;
+        }
+        for column in &self.removed_columns {
+            writeln!(f, "    - column {}", column)?;
+        }
+        for change in &self.changed_columns {
+            writeln!(f, "    ~ column {}: {} -> {}", change.column, change.old, change.new)?;
+        }
+        for row in &self.added_rows {
+            writeln!(f, "    + row {}", row)?;
+        }
+        for row in &self.removed_rows {
+            writeln!(f, "    - row {}", row)?;
+        }
+        for change in &self.changed_cells {
+            writeln!(f, "    ~ row {} {}: {:?} -> {:?}", change.row, change.column, change.old, change.new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::excel::diff::ConfigDiff;
+    use crate::excel::excel_define::{CellType, GameConfig, GameConfigs, KeyType};
+
+    fn config(name: &str, cell_name: Vec<&str>, cell_type: Vec<CellType>, data: Vec<Vec<&str>>) -> GameConfig {
+        let mut key_type = vec![KeyType::All; cell_name.len()];
+        key_type[0] = KeyType::AllKey;
+        GameConfig::builder()
+            .name(name.to_string())
+            .cell_name(cell_name.into_iter().map(ToString::to_string).collect())
+            .key_type(key_type)
+            .cell_type(cell_type)
+            .data(data.into_iter().map(|row| { row.into_iter().map(ToString::to_string).collect() }).collect())
+            .build()
+    }
+
+    fn configs(data: Vec<GameConfig>) -> GameConfigs {
+        GameConfigs::builder().commit_id(String::new()).create_mills(0).data(data).build()
+    }
+
+    #[test]
+    fn test_diff() -> anyhow::Result<()> {
+        let old = configs(vec![
+            config("item", vec!["id", "name", "price"], vec![CellType::UInt, CellType::String, CellType::UInt], vec![vec!["1001", "sword", "10"], vec!["1002", "shield", "20"]]),
+            config("shop", vec!["id"], vec![CellType::UInt], vec![vec!["1"]]),
+        ]);
+        let new = configs(vec![
+            config("item", vec!["id", "weight", "name", "price"], vec![CellType::UInt, CellType::Float, CellType::String, CellType::Int], vec![vec!["1001", "1.5", "great sword", "10"], vec!["1003", "2", "bow", "30"]]),
+            config("level", vec!["id"], vec![CellType::UInt], vec![vec!["1"]]),
+        ]);
+        let diff = ConfigDiff::diff(&old, &new);
+        assert_eq!(diff.to_string(), r#"+ sheet level
+- sheet shop
+~ sheet item
+    + column weight
+    ~ column price: uint all -> int all
+    + row 1003
+    - row 1002
+    ~ row 1001 name: "sword" -> "great sword"
+"#);
+        let json = serde_json::to_value(&diff)?;
+        assert_eq!(json["changed_sheets"][0]["changed_cells"][0]["new"], "great sword");
+        assert!(ConfigDiff::diff(&new, &new).is_empty());
+        Ok(())
+    }
+}
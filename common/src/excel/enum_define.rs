@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+
+use crate::excel::convert::{Parse, ToLua};
+use crate::excel::excel_define::{CellType, GameConfig};
+
+/// sheets with this name define enums instead of configs, the columns `enum`, `name` and `value` are required,
+/// the generated lua module of all enums has the same name
+pub const ENUM_SHEET: &str = "enum";
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EnumMember {
+    pub name: String,
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EnumDefine {
+    pub name: String,
+    /// workbook or `.proto` file the enum is defined in
+    pub source: String,
+    pub members: Vec<EnumMember>,
+}
+
+impl EnumDefine {
+    /// member written in a cell, either its name or its value
+    pub fn member(&self, token: &str) -> Option<&EnumMember> {
+        match token.parse::<i32>() {
+            Ok(value) => self.members.iter().find(|member| { member.value == value }),
+            Err(_) => self.members.iter().find(|member| { member.name == token }),
+        }
+    }
+}
+
+/// enums that `enum[Name]` and `flags[Name]` cells are resolved against, from enum sheets and `.proto` files
+#[derive(Debug, Clone, Default)]
+pub struct EnumDefines {
+    pub enums: BTreeMap<String, EnumDefine>,
+}
+
+impl EnumDefines {
+    pub fn is_empty(&self) -> bool {
+        self.enums.is_empty()
+    }
+
+    /// one member per row, an enum may not be split across workbooks
+    pub fn add_sheet(&mut self, config: &GameConfig) -> Vec<anyhow::Error> {
+        let mut errors = vec![];
+        let columns = (config.column_index("enum", &CellType::String), config.column_index("name", &CellType::String), config.column_index("value", &CellType::Int));
+        let (enum_index, name_index, value_index) = match columns {
+            (Ok(enum_index), Ok(name_index), Ok(value_index)) => (enum_index, name_index, value_index),
+            (enum_index, name_index, value_index) => {
+                return [enum_index, name_index, value_index].into_iter().filter_map(Result::err).map(|error| { config.sheet_error(error) }).collect();
+            }
+        };
+        for (i, row) in config.data.iter().enumerate() {
+            let enum_name = row[enum_index].trim();
+            let member = row[name_index].trim();
+            if enum_name.is_empty() {
+                errors.push(config.cell_error(i, enum_index, "enum name is empty"));
+                continue;
+            }
+            if !is_identifier(member) {
+                errors.push(config.cell_error(i, name_index, "member name must start with a letter or _ and contain only letters, digits and _"));
+                continue;
+            }
+            // values that cannot be parsed are already reported by the type check
+            let value: i32 = match row[value_index].parse() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            if let Err(error) = self.add_member(enum_name, &config.source, member, value) {
+                errors.push(config.cell_error(i, name_index, error));
+            }
+        }
+        errors
+    }
+
+    /// every enum of a `.proto` file, including the ones nested in messages, e.g. `Language` of the proto crate
+    pub fn add_proto<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).context(format!("failed to read: {}", path.display()))?;
+        let source = path.display().to_string();
+        let comment = regex::Regex::new(r"(?s)//[^\n]*|/\*.*?\*/")?;
+        let enum_block = regex::Regex::new(r"\benum\s+(\w+)\s*\{([^}]*)\}")?;
+        let enum_value = regex::Regex::new(r"(\w+)\s*=\s*(-?\d+)")?;
+        let content = comment.replace_all(&content, "");
+        for block in enum_block.captures_iter(&content) {
+            for value in enum_value.captures_iter(&block[2]) {
+                let number = value[2].parse().context(format!("incorrect value of {}.{} in {}", &block[1], &value[1], source))?;
+                self.add_member(&block[1], &source, &value[1], number)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_member(&mut self, enum_name: &str, source: &str, member: &str, value: i32) -> anyhow::Result<()> {
+        let define = self.enums.entry(enum_name.to_string()).or_insert_with(|| {
+            EnumDefine {
+                name: enum_name.to_string(),
+                source: source.to_string(),
+                members: vec![],
+            }
+        });
+        if define.source != source {
+            return Err(anyhow!(format!("enum {} is already defined in {}", enum_name, define.source)));
+        }
+        if define.members.iter().any(|m| { m.name == member }) {
+            return Err(anyhow!(format!("duplicate member {} of enum {}", member, enum_name)));
+        }
+        if let Some(other) = define.members.iter().find(|m| { m.value == value }) {
+            return Err(anyhow!(format!("member {} of enum {} has the same value {} as {}", member, enum_name, value, other.name)));
+        }
+        define.members.push(EnumMember { name: member.to_string(), value });
+        Ok(())
+    }
+
+    /// the integer written for an enum or flags cell, flags are or-ed together, other types are returned as is
+    pub fn resolve(&self, ty: &CellType, data: &str) -> anyhow::Result<String> {
+        let (name, flags) = match ty {
+            CellType::Enum(name) => (name, false),
+            CellType::Flags(name) => (name, true),
+            _ => return Ok(data.to_string()),
+        };
+        let define = self.enums.get(name).ok_or_else(|| { anyhow!(format!("enum {} is not defined", name)) })?;
+        let tokens = enum_tokens(ty, data)?;
+        if tokens.is_empty() {
+            return Ok(String::new());
+        }
+        let mut value = 0;
+        for token in tokens {
+            let member = define.member(token).ok_or_else(|| { anyhow!(format!("{} is not a member of {}", token, name)) })?;
+            if flags {
+                if member.value < 0 || member.value.count_ones() > 1 {
+                    return Err(anyhow!(format!("member {} of {} is not a flag, the value {} must be 0 or a power of 2", member.name, name, member.value)));
+                }
+                value |= member.value;
+            } else {
+                value = member.value;
+            }
+        }
+        Ok(value.to_string())
+    }
+
+    /// replace the enum and flags cells of the config with their integer values
+    pub fn resolve_config(&self, config: &mut GameConfig) -> Vec<anyhow::Error> {
+        let mut errors = vec![];
+        for j in 0..config.cell_type.len() {
+            let ty = config.cell_type[j].clone();
+            let name = match &ty {
+                CellType::Enum(name) | CellType::Flags(name) => name,
+                _ => continue,
+            };
+            if !self.enums.contains_key(name) {
+                errors.push(config.column_error(j, format!("enum {} is not defined", name)));
+                continue;
+            }
+            for i in 0..config.data.len() {
+                // cells that cannot be parsed are already reported by the type check
+                if enum_tokens(&ty, &config.data[i][j]).is_err() {
+                    continue;
+                }
+                match self.resolve(&ty, &config.data[i][j]) {
+                    Ok(value) => config.data[i][j] = value,
+                    Err(error) => errors.push(config.cell_error(i, j, error)),
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// the members written in an enum or flags cell, names or values, flags are separated by `|`, e.g. `Fire|Water`
+pub fn enum_tokens<'a>(ty: &CellType, data: &'a str) -> anyhow::Result<Vec<&'a str>> {
+    let data = data.trim();
+    if data.is_empty() {
+        return Ok(vec![]);
+    }
+    let tokens = match ty {
+        CellType::Flags(_) => data.split("|").map(|token| { token.trim() }).collect(),
+        _ => vec![data],
+    };
+    for token in &tokens {
+        if !is_identifier(token) && token.parse::<i32>().is_err() {
+            return Err(anyhow!(format!("incorrect member: {:?}, expect a name or a value of {}", token, ty)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().map(|c| { c.is_ascii_alphabetic() || c == '_' }).unwrap_or(false) && name.chars().all(|c| { c.is_ascii_alphanumeric() || c == '_' })
+}
+
+/// a read only table of `{ Enum = { Member = value } }`, loaded like a config named `ENUM_SHEET`
+impl ToLua for EnumDefines {
+    type Output = String;
+
+    fn to_lua(&self) -> anyhow::Result<Self::Output> {
+        let mut formatted_enums = vec![];
+        for define in self.enums.values() {
+            let mut formatted_members = vec![];
+            for member in &define.members {
+                formatted_members.push(format!("[{}] = {}", member.name.to_lua()?, member.value));
+            }
+            formatted_enums.push(format!("    [{}] = {{ {} }}", define.name.to_lua()?, formatted_members.join(", ")));
+        }
+        Ok(format!(r#"
+local enums = {{
+{}
+}}
+
+local config = {{ name = '{}' }}
+
+setmetatable(config, {{
+    __index = enums,
+    __newindex = function()
+        error("Attempt to modify read-only table")
+    end
+}})
+
+return config
+        "#, formatted_enums.join(",\n"), ENUM_SHEET).trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use crate::excel::convert::ToLua;
+    use crate::excel::enum_define::EnumDefines;
+    use crate::excel::excel_define::{CellType, GameConfig, KeyType};
+
+    #[test]
+    fn test_resolve_enums() -> anyhow::Result<()> {
+        let sheet = GameConfig::builder()
+            .name("enum".to_string())
+            .cell_name(vec!["enum".to_string(), "name".to_string(), "value".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::AllKey, KeyType::All])
+            .cell_type(vec![CellType::String, CellType::String, CellType::Int])
+            .data(vec![
+                vec!["Quality".to_string(), "Rare".to_string(), "2".to_string()],
+                vec!["Quality".to_string(), "Epic".to_string(), "3".to_string()],
+                vec!["Element".to_string(), "Fire".to_string(), "1".to_string()],
+                vec!["Element".to_string(), "Water".to_string(), "2".to_string()],
+                vec!["Element".to_string(), "Water".to_string(), "4".to_string()],
+            ])
+            .source("enum.xlsx".to_string())
+            .build();
+        let mut enums = EnumDefines::default();
+        let errors = enums.add_sheet(&sheet).iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors, vec![r#"enum.xlsx [enum] B10 name(string) data: "Water": duplicate member Water of enum Element"#]);
+
+        let proto = env::temp_dir().join("test_resolve_enums.proto");
+        std::fs::write(&proto, "// enum Commented { A = 1; }\nenum Language {\n  option allow_alias = false;\n  Zh = 0;\n  En = 1; /* english */\n}\n")?;
+        enums.add_proto(&proto)?;
+        assert_eq!(enums.enums["Language"].members.len(), 2);
+        assert!(!enums.enums.contains_key("Commented"));
+        assert!(enums.add_proto(&proto).is_err());
+
+        let mut item = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["quality".to_string(), "element".to_string(), "language".to_string()])
+            .key_type(vec![KeyType::All, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::Enum("Quality".to_string()), CellType::Flags("Element".to_string()), CellType::Enum("Language".to_string())])
+            .data(vec![
+                vec!["Epic".to_string(), "Fire|Water".to_string(), "En".to_string()],
+                vec!["2".to_string(), "".to_string(), "Fr".to_string()],
+                vec!["Legendary".to_string(), "Fire | 2".to_string(), "a b".to_string()],
+            ])
+            .build();
+        let errors = enums.resolve_config(&mut item).iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors, vec![
+            r#" [item] A8 quality(enum[Quality]) data: "Legendary": Legendary is not a member of Quality"#,
+            r#" [item] C7 language(enum[Language]) data: "Fr": Fr is not a member of Language"#,
+        ]);
+        assert_eq!(item.data[0], vec!["3", "3", "1"]);
+        assert_eq!(item.data[1][..2], ["2", ""]);
+        assert_eq!(item.data[2][1], "3");
+        assert!(enums.to_lua()?.contains(r#"    ["Quality"] = { ["Rare"] = 2, ["Epic"] = 3 }"#));
+        Ok(())
+    }
+}
@@ -1,12 +1,19 @@
-use anyhow::anyhow;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-use crate::excel::convert::{LuaWriter, ToLua};
+use anyhow::{anyhow, Context};
+
+use crate::excel::convert::{JsonWriter, LuaWriter, ToLua};
+use crate::excel::lua_emitter::{lua_string, LuaOptions, SharedTables};
+use crate::excel::report::{CellLocation, column_letter, ExcelError};
+use crate::excel::revision::sha256_hex;
+use crate::excel::type_parser::TypeParser;
 use crate::excel::excel_define::KeyType::{All, AllKey, Client, ClientKey, Server, ServerKey};
 
-#[derive(strum::EnumString, strum::Display, strum::EnumIter, Debug, serde::Deserialize, serde::Serialize, Clone)]
-#[strum(serialize_all = "snake_case")]
+/// type of a column, written in the 2nd header row and parsed by `TypeParser`
+#[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq, Clone)]
 pub enum CellType {
-    #[strum(serialize = "uint")]
     UInt,
     Int,
     Long,
@@ -14,27 +21,191 @@ pub enum CellType {
     Bool,
     Vector3ArrayInt,
     Vector3Int,
-    #[strum(serialize = "vector2_int", serialize = "vector[int,int]")]
     Vector2Int,
-    #[strum(serialize = "vector3_uint")]
     Vector3UInt,
-    #[strum(serialize = "vector2_uint")]
     Vector2UInt,
     Vector2ArrayInt,
     ArrayInt,
-    #[strum(serialize = "array_uint")]
     ArrayUInt,
     DictionaryStringFloat,
     DictionaryStringInt,
     Lang,
     Float,
     Double,
-    #[strum(serialize = "vector[float,float]")]
     Vector2Float,
-    #[strum(serialize = "vector[float,float,float]")]
     Vector3Float,
-    #[strum(serialize = "vector[string,string]")]
     Vector2String,
+    /// one member of an enum, written as `enum[Quality]`, see `EnumDefines`
+    Enum(String),
+    /// members of a flag enum joined by `|`, written as `flags[Element]`
+    Flags(String),
+    /// written as `array<uint>`, cells look like `[1,2,3]`, see `CompositeValue` for the cell syntax
+    Array(Box<CellType>),
+    /// written as `struct{id:uint,count:int}`, cells list the fields in order, e.g. `{1001,2}`
+    Struct(Vec<StructField>),
+    /// `2023-03-12 10:00:00` in the server timezone, exported as unix seconds, see `time_define`
+    DateTime,
+    /// `1h30m` or seconds, exported as seconds
+    Duration,
+    /// cron like schedule `0 5 * * 1-5`, exported as written
+    Cron,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub struct StructField {
+    pub name: String,
+    pub ty: CellType,
+}
+
+/// names of the types without parameters, the first name of a type is the one it is displayed with
+pub const NAMED_TYPES: [(&str, CellType); 25] = [
+    ("uint", CellType::UInt),
+    ("int", CellType::Int),
+    ("long", CellType::Long),
+    ("string", CellType::String),
+    ("bool", CellType::Bool),
+    ("vector3_array_int", CellType::Vector3ArrayInt),
+    ("vector3_int", CellType::Vector3Int),
+    ("vector[int,int]", CellType::Vector2Int),
+    ("vector2_int", CellType::Vector2Int),
+    ("vector3_uint", CellType::Vector3UInt),
+    ("vector2_uint", CellType::Vector2UInt),
+    ("vector2_array_int", CellType::Vector2ArrayInt),
+    ("array_int", CellType::ArrayInt),
+    ("array_uint", CellType::ArrayUInt),
+    ("dictionary_string_float", CellType::DictionaryStringFloat),
+    ("dictionary_string_int", CellType::DictionaryStringInt),
+    ("lang", CellType::Lang),
+    ("float", CellType::Float),
+    ("double", CellType::Double),
+    ("vector[float,float]", CellType::Vector2Float),
+    ("vector[float,float,float]", CellType::Vector3Float),
+    ("vector[string,string]", CellType::Vector2String),
+    ("datetime", CellType::DateTime),
+    ("duration", CellType::Duration),
+    ("cron", CellType::Cron),
+];
+
+impl Display for CellType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CellType::Enum(name) => write!(f, "enum[{}]", name),
+            CellType::Flags(name) => write!(f, "flags[{}]", name),
+            CellType::Array(element) => write!(f, "array<{}>", element),
+            CellType::Struct(fields) => {
+                let fields = fields.iter().map(|field| { format!("{}:{}", field.name, field.ty) }).collect::<Vec<_>>();
+                write!(f, "struct{{{}}}", fields.join(","))
+            }
+            other => {
+                let (name, _) = NAMED_TYPES.iter().find(|(_, ty)| { ty == other }).expect("every type without parameters is named");
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+impl CellType {
+    /// cell type of the 2nd header row
+    pub fn parse_type(s: &str) -> anyhow::Result<CellType> {
+        TypeParser::new(s).parse()
+    }
+
+    /// array and struct, the cells are parsed into a `CompositeValue`
+    pub fn is_composite(&self) -> bool {
+        matches!(self, CellType::Array(_) | CellType::Struct(_))
+    }
+
+    /// `lang`, or an array or struct with a `lang` value somewhere in it
+    pub fn has_lang(&self) -> bool {
+        match self {
+            CellType::Lang => true,
+            CellType::Array(element) => element.has_lang(),
+            CellType::Struct(fields) => fields.iter().any(|field| { field.ty.has_lang() }),
+            _ => false,
+        }
+    }
+
+    /// scalars that can be nested in arrays and structs
+    pub fn is_composite_scalar(&self) -> bool {
+        matches!(self, CellType::UInt | CellType::Int | CellType::Long | CellType::String | CellType::Bool | CellType::Lang | CellType::Float | CellType::Double)
+    }
+
+    /// rust type used by generated code, aliases are defined in `convert.rs`, composite types are generated per column by `RustWriter`
+    pub fn rust_type(&self) -> Option<&'static str> {
+        let rust_type = match self {
+            CellType::UInt => "u32",
+            CellType::Int => "i32",
+            CellType::Long => "i64",
+            CellType::String => "String",
+            CellType::Bool => "bool",
+            CellType::Vector3ArrayInt => "Vector3ArrayInt",
+            CellType::Vector3Int => "Vector3Int",
+            CellType::Vector2Int => "Vector2Int",
+            CellType::Vector3UInt => "Vector3UInt",
+            CellType::Vector2UInt => "Vector2UInt",
+            CellType::Vector2ArrayInt => "Vector2ArrayInt",
+            CellType::ArrayInt => "ArrayInt",
+            CellType::ArrayUInt => "ArrayUInt",
+            CellType::DictionaryStringFloat => "DictionaryStringFloat",
+            CellType::DictionaryStringInt => "DictionaryStringInt",
+            CellType::Lang => "Lang",
+            CellType::Float => "f32",
+            CellType::Double => "f64",
+            CellType::Vector2Float => "Vector2Float",
+            CellType::Vector3Float => "Vector3Float",
+            CellType::Vector2String => "Vector2String",
+            CellType::Enum(_) | CellType::Flags(_) => "i32",
+            CellType::DateTime => "DateTime",
+            CellType::Duration => "Duration",
+            CellType::Cron => "CronSchedule",
+            CellType::Array(_) | CellType::Struct(_) => return None,
+        };
+        Some(rust_type)
+    }
+
+    /// proto3 field type used by `ProtoWriter`, vector messages are defined in `excel_types.proto`,
+    /// composite types are generated per column by `ProtoWriter`
+    pub fn proto_type(&self) -> Option<&'static str> {
+        let proto_type = match self {
+            CellType::UInt => "uint32",
+            CellType::Int => "int32",
+            CellType::Long => "int64",
+            CellType::String => "string",
+            CellType::Bool => "bool",
+            CellType::Vector3ArrayInt => "repeated Vector3Int",
+            CellType::Vector3Int => "Vector3Int",
+            CellType::Vector2Int => "Vector2Int",
+            CellType::Vector3UInt => "Vector3UInt",
+            CellType::Vector2UInt => "Vector2UInt",
+            CellType::Vector2ArrayInt => "repeated Vector2Int",
+            CellType::ArrayInt => "repeated int32",
+            CellType::ArrayUInt => "repeated uint32",
+            CellType::DictionaryStringFloat => "map<string, float>",
+            CellType::DictionaryStringInt => "map<string, int32>",
+            CellType::Lang => "string",
+            CellType::Float => "float",
+            CellType::Double => "double",
+            CellType::Vector2Float => "Vector2Float",
+            CellType::Vector3Float => "Vector3Float",
+            CellType::Vector2String => "Vector2String",
+            CellType::Enum(_) | CellType::Flags(_) => "int32",
+            CellType::DateTime => "int64",
+            CellType::Duration => "uint64",
+            CellType::Cron => "string",
+            CellType::Array(_) | CellType::Struct(_) => return None,
+        };
+        Some(proto_type)
+    }
+
+    /// whether an empty cell is a value of the type, an empty string or collection, other types need a default
+    pub fn has_empty_value(&self) -> bool {
+        matches!(self, CellType::String | CellType::Lang | CellType::Vector3ArrayInt | CellType::Vector2ArrayInt | CellType::ArrayInt | CellType::ArrayUInt | CellType::DictionaryStringFloat | CellType::DictionaryStringInt | CellType::Flags(_) | CellType::Array(_))
+    }
+
+    /// whether the rust type implements `Hash + Eq` and can be used as a table key
+    pub fn hashable(&self) -> bool {
+        !matches!(self, CellType::DictionaryStringFloat | CellType::DictionaryStringInt | CellType::Float | CellType::Double | CellType::Vector2Float | CellType::Vector3Float | CellType::Array(_) | CellType::Struct(_) | CellType::Cron)
+    }
 }
 
 #[derive(strum::EnumString, strum::Display, strum::EnumIter, Debug, serde::Deserialize, serde::Serialize, PartialEq, Clone)]
@@ -69,39 +240,470 @@ impl KeyType {
     }
 }
 
-#[derive(typed_builder::TypedBuilder, Debug, serde::Deserialize, serde::Serialize)]
+/// validation rule of a column, declared in the 4th header row separated by `;`, e.g. `range:1..=100;unique`
+///
+/// `regex:` and `default:` take the rest of the cell so the pattern or value may contain `;`, empty cells are only
+/// checked by `non_empty`, which is implied for types without an empty value unless the column is `nullable` or has a `default:`
+///
+/// rules are checked on the text written in the workbook, before datetime, duration, enum and lang cells are resolved,
+/// the exported bundle only has its cells type checked when loaded
+#[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub enum CellRule {
+    /// every number of the cell, `a..b` excludes `b`, `a..=b` includes it, either bound may be left out
+    Range { min: Option<f64>, max: Option<f64>, inclusive: bool },
+    NonEmpty,
+    /// every value of the cell is one of the listed values, e.g. `enum:1,2,3`
+    Enum(Vec<String>),
+    Regex(String),
+    /// no two rows have the same value in this column
+    Unique,
+    /// empty cells are exported as `nil` in lua, `null` in json and `None` in rust
+    Nullable,
+    /// value of the empty cells, e.g. `default:1`
+    Default(String),
+}
+
+impl CellRule {
+    pub fn parse_rules(s: &str) -> anyhow::Result<Vec<CellRule>> {
+        let mut rules = vec![];
+        let mut rest = s.trim();
+        while !rest.is_empty() {
+            if rest.starts_with("regex:") || rest.starts_with("default:") {
+                rules.push(CellRule::from_str(rest)?);
+                break;
+            }
+            let (rule, remain) = rest.split_once(";").unwrap_or((rest, ""));
+            rest = remain.trim();
+            let rule = rule.trim();
+            if !rule.is_empty() {
+                rules.push(CellRule::from_str(rule)?);
+            }
+        }
+        Ok(rules)
+    }
+
+    /// range needs numbers, enum, flags, array and struct cells only take `non_empty` and `unique`
+    pub fn accept(&self, ty: &CellType) -> bool {
+        match (self, ty) {
+            (CellRule::NonEmpty | CellRule::Unique | CellRule::Nullable | CellRule::Default(_), _) => true,
+            (_, CellType::Enum(_) | CellType::Flags(_) | CellType::Array(_) | CellType::Struct(_)) => false,
+            (CellRule::Range { .. }, _) => !matches!(ty, CellType::String | CellType::Lang | CellType::Bool | CellType::Vector2String | CellType::DateTime | CellType::Cron),
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for CellRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = s.split_once(":").map(|(name, arg)| { (name.trim(), Some(arg.trim())) }).unwrap_or((s.trim(), None));
+        match (name, arg) {
+            ("non_empty", None) => Ok(CellRule::NonEmpty),
+            ("unique", None) => Ok(CellRule::Unique),
+            ("nullable", None) => Ok(CellRule::Nullable),
+            ("default", Some(value)) => Ok(CellRule::Default(value.to_string())),
+            ("enum", Some(values)) if !values.is_empty() => Ok(CellRule::Enum(values.split(",").map(|v| { v.trim().to_string() }).collect())),
+            ("regex", Some(pattern)) => {
+                regex::Regex::new(pattern).map_err(|e| { anyhow!(format!("incorrect regex rule: {}, {}", pattern, e)) })?;
+                Ok(CellRule::Regex(pattern.to_string()))
+            }
+            ("range", Some(range)) => {
+                let (min, max) = range.split_once("..").ok_or_else(|| { anyhow!(format!("incorrect range rule: {}, expect min..max", s)) })?;
+                let (max, inclusive) = match max.strip_prefix("=") {
+                    None => (max, false),
+                    Some(max) => (max, true),
+                };
+                let bound = |b: &str| -> anyhow::Result<Option<f64>> {
+                    if b.trim().is_empty() {
+                        Ok(None)
+                    } else {
+                        Ok(Some(b.trim().parse().map_err(|_| { anyhow!(format!("incorrect range rule: {}, bound {} is not a number", s, b)) })?))
+                    }
+                };
+                Ok(CellRule::Range { min: bound(min)?, max: bound(max)?, inclusive })
+            }
+            _ => Err(anyhow!(format!("unknown rule: {}, expect range:min..max, non_empty, enum:a,b, regex:pattern, unique, nullable or default:value", s))),
+        }
+    }
+}
+
+impl Display for CellRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CellRule::Range { min, max, inclusive } => {
+                let bound = |b: &Option<f64>| { b.map(|b| { b.to_string() }).unwrap_or_default() };
+                write!(f, "range:{}..{}{}", bound(min), if *inclusive { "=" } else { "" }, bound(max))
+            }
+            CellRule::NonEmpty => write!(f, "non_empty"),
+            CellRule::Enum(values) => write!(f, "enum:{}", values.join(",")),
+            CellRule::Regex(pattern) => write!(f, "regex:{}", pattern),
+            CellRule::Unique => write!(f, "unique"),
+            CellRule::Nullable => write!(f, "nullable"),
+            CellRule::Default(value) => write!(f, "default:{}", value),
+        }
+    }
+}
+
+/// which columns a config keeps, `All` is a sheet as read from excel before it is split into bundles
+#[derive(strum::Display, Debug, Default, serde::Deserialize, serde::Serialize, PartialEq, Clone, Copy)]
+#[strum(serialize_all = "snake_case")]
+pub enum ExportSide {
+    #[default]
+    All,
+    Server,
+    Client,
+}
+
+impl ExportSide {
+    pub fn columns(&self) -> Vec<KeyType> {
+        match self {
+            ExportSide::All => vec![AllKey, All, Client, ClientKey, Server, ServerKey],
+            ExportSide::Server => KeyType::server_side(),
+            ExportSide::Client => KeyType::client_side(),
+        }
+    }
+
+    /// key column sets in priority order, the first one found in the sheet is used
+    pub fn keys(&self) -> Vec<Vec<KeyType>> {
+        match self {
+            ExportSide::All => vec![KeyType::server_key(), KeyType::client_key()],
+            ExportSide::Server => vec![KeyType::server_key()],
+            ExportSide::Client => vec![KeyType::client_key()],
+        }
+    }
+}
+
+/// reference annotation of a column, written as `ref:sheet.column` after the cell type, e.g. `array_uint|ref:item.id`
+#[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub struct CellRef {
+    pub sheet: String,
+    pub column: String,
+}
+
+impl FromStr for CellRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let target = s.trim().strip_prefix("ref:").ok_or(anyhow!(format!("incorrect reference annotation: {}, expect ref:sheet.column", s)))?;
+        let (sheet, column) = target.split_once(".").ok_or(anyhow!(format!("incorrect reference annotation: {}, expect ref:sheet.column", s)))?;
+        if sheet.trim().is_empty() || column.trim().is_empty() {
+            return Err(anyhow!(format!("incorrect reference annotation: {}, expect ref:sheet.column", s)));
+        }
+        Ok(CellRef { sheet: sheet.trim().to_string(), column: column.trim().to_string() })
+    }
+}
+
+impl Display for CellRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ref:{}.{}", self.sheet, self.column)
+    }
+}
+
+#[derive(typed_builder::TypedBuilder, Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct GameConfig {
     pub name: String,
     pub cell_name: Vec<String>,
     pub key_type: Vec<KeyType>,
     pub cell_type: Vec<CellType>,
+    #[builder(default)]
+    pub cell_ref: Vec<Option<CellRef>>,
+    #[builder(default)]
+    pub cell_rule: Vec<Vec<CellRule>>,
     pub data: Vec<Vec<String>>,
+    /// workbook the sheet was read from, only known while exporting
+    #[serde(skip)]
+    #[builder(default)]
+    pub source: String,
+    /// excel row number of every data row, only known while exporting
+    #[serde(skip)]
+    #[builder(default)]
+    pub row_number: Vec<usize>,
+    /// excel column number of every column, only known while exporting
+    #[serde(skip)]
+    #[builder(default)]
+    pub column_number: Vec<usize>,
+    #[builder(default)]
+    pub side: ExportSide,
 }
 
+/// separator between the values of a composite key, e.g. `1001_2`
+pub const COMPOSITE_KEY_SEPARATOR: &str = "_";
+
 #[derive(typed_builder::TypedBuilder, Debug, Default, serde::Deserialize, serde::Serialize)]
 pub struct GameConfigs {
     pub commit_id: String,
     pub create_mills: u128,
     pub data: Vec<GameConfig>,
+    /// sha256 of the exported data, see `GameConfigs::compute_digest`
+    #[builder(default)]
+    pub digest: String,
+}
+
+impl GameConfigs {
+    /// the bundle exported to `side`, sheets without any column of that side are left out
+    pub fn for_side(&self, side: ExportSide) -> GameConfigs {
+        GameConfigs {
+            commit_id: self.commit_id.clone(),
+            create_mills: self.create_mills,
+            data: self.data.iter().map(|config| { config.for_side(side) }).filter(|config| { !config.key_type.is_empty() }).collect(),
+            digest: String::new(),
+        }
+    }
+
+    /// digest of the exported data only, source locations and export time are not included
+    pub fn compute_digest(&self) -> anyhow::Result<String> {
+        let encoded = bincode::serialize(&self.data).context("failed to serialize GameConfigs data")?;
+        Ok(sha256_hex(&encoded))
+    }
+}
+
+impl GameConfig {
+    pub fn digest(&self) -> anyhow::Result<String> {
+        let encoded = bincode::serialize(self).context(format!("failed to serialize GameConfig: {}", self.name))?;
+        Ok(sha256_hex(&encoded))
+    }
+
+    /// rows as objects keyed by column name with typed values, the digest identifies the data since unchanged configs
+    /// are not rewritten by an incremental export
+    pub fn to_json(&self) -> anyhow::Result<serde_json::Value> {
+        let mut rows = Vec::with_capacity(self.data.len());
+        for (i, row_data) in self.data.iter().enumerate() {
+            let mut row = serde_json::Map::new();
+            for (j, (cell_data, ty)) in row_data.iter().zip(&self.cell_type).enumerate() {
+                let value = if self.is_null(i, j) { serde_json::Value::Null } else { JsonWriter::write_at(ty, cell_data, &self.cell_location(i, j))? };
+                row.insert(self.cell_name[j].clone(), value);
+            }
+            rows.push(serde_json::Value::Object(row));
+        }
+        let key_names = self.lua_key_indexes()?.into_iter().map(|i| { self.cell_name[i].clone() }).collect::<Vec<_>>();
+        Ok(serde_json::json!({
+            "name": self.name,
+            "digest": self.digest()?,
+            "keys": key_names,
+            "rows": rows,
+        }))
+    }
+
+    pub fn nullable(&self, column: usize) -> bool {
+        self.cell_rule.get(column).map(|rules| { rules.contains(&CellRule::Nullable) }).unwrap_or(false)
+    }
+
+    pub fn default_value(&self, column: usize) -> Option<&String> {
+        self.cell_rule.get(column)?.iter().find_map(|rule| {
+            match rule {
+                CellRule::Default(value) => Some(value),
+                _ => None,
+            }
+        })
+    }
+
+    /// empty cells of nullable columns, they are left empty and exported as missing values
+    pub fn is_null(&self, row: usize, column: usize) -> bool {
+        self.data[row][column].is_empty() && self.nullable(column)
+    }
+
+    /// fill the empty cells of columns with a `default:` rule, done after the check so the default is checked once
+    pub fn apply_defaults(&mut self) {
+        for column in 0..self.cell_type.len() {
+            if let Some(value) = self.default_value(column).cloned() {
+                for row in &mut self.data {
+                    if row[column].is_empty() {
+                        row[column] = value.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    /// only the columns exported to `side`
+    pub fn for_side(&self, side: ExportSide) -> GameConfig {
+        let columns = self.key_indexes(&side.columns());
+        let pick = |values: &[String]| -> Vec<String> { columns.iter().map(|i| { values[*i].clone() }).collect() };
+        GameConfig {
+            name: self.name.clone(),
+            cell_name: pick(&self.cell_name),
+            key_type: columns.iter().map(|i| { self.key_type[*i].clone() }).collect(),
+            cell_type: columns.iter().map(|i| { self.cell_type[*i].clone() }).collect(),
+            cell_ref: columns.iter().filter_map(|i| { self.cell_ref.get(*i).cloned() }).collect(),
+            cell_rule: columns.iter().filter_map(|i| { self.cell_rule.get(*i).cloned() }).collect(),
+            data: self.data.iter().map(|row| { pick(row) }).collect(),
+            source: self.source.clone(),
+            row_number: self.row_number.clone(),
+            column_number: columns.iter().filter_map(|i| { self.column_number.get(*i).copied() }).collect(),
+            side,
+        }
+    }
+
+    /// excel row number of a data row, assuming the sheet starts at `A1` if unknown
+    pub fn row_number_of(&self, row: usize) -> usize {
+        self.row_number.get(row).copied().unwrap_or(row + 6)
+    }
+
+    pub fn cell_location(&self, row: usize, column: usize) -> CellLocation {
+        CellLocation {
+            file: self.source.clone(),
+            sheet: self.name.clone(),
+            row: self.row_number_of(row),
+            column: column_letter(self.column_number.get(column).copied().unwrap_or(column + 1)),
+            column_name: self.cell_name[column].clone(),
+            cell_type: self.cell_type[column].to_string(),
+        }
+    }
+
+    pub fn cell_error(&self, row: usize, column: usize, message: impl ToString) -> anyhow::Error {
+        let location = self.cell_location(row, column);
+        ExcelError::at(&location, &self.data[row][column], message).into()
+    }
+
+    pub fn column_error(&self, column: usize, message: impl ToString) -> anyhow::Error {
+        ExcelError {
+            column: Some(column_letter(self.column_number.get(column).copied().unwrap_or(column + 1))),
+            column_name: Some(self.cell_name[column].clone()),
+            cell_type: Some(self.cell_type[column].to_string()),
+            ..ExcelError::sheet(&self.source, &self.name, message)
+        }.into()
+    }
+
+    pub fn sheet_error(&self, message: impl ToString) -> anyhow::Error {
+        ExcelError::sheet(&self.source, &self.name, message).into()
+    }
+
+    /// index of the column with the given name, failing if its type is not the expected one
+    pub fn column_index(&self, name: &str, ty: &CellType) -> anyhow::Result<usize> {
+        let (index, _) = self.cell_name.iter().enumerate().find(|(_, n)| { *n == name }).ok_or(anyhow!(format!("{} column {} not found", self.name, name)))?;
+        if self.cell_type[index] != *ty {
+            return Err(anyhow!(format!("{} column {} expect type {}, got: {}", self.name, name, ty, self.cell_type[index])));
+        }
+        Ok(index)
+    }
+
+    /// all columns marked as one of `keys`, more than one column makes a composite key
+    pub fn key_indexes(&self, keys: &[KeyType]) -> Vec<usize> {
+        self.key_type.iter().enumerate().filter(|(_, key)| { keys.contains(key) }).map(|(i, _)| { i }).collect()
+    }
+
+    /// key columns of the side this config is exported to, see `ExportSide::keys`
+    pub fn side_key_indexes(&self) -> Vec<usize> {
+        for keys in self.side.keys() {
+            let key_indexes = self.key_indexes(&keys);
+            if !key_indexes.is_empty() {
+                return key_indexes;
+            }
+        }
+        vec![]
+    }
+
+    /// key columns of the exported lua table: the side keys, or the first `All` column
+    pub fn lua_key_indexes(&self) -> anyhow::Result<Vec<usize>> {
+        let mut key_indexes = self.side_key_indexes();
+        if key_indexes.is_empty() {
+            key_indexes = self.key_indexes(&[All]).into_iter().take(1).collect();
+        }
+        if key_indexes.is_empty() {
+            return Err(self.sheet_error("allkey|serverkey|clientkey not found"));
+        }
+        Ok(key_indexes)
+    }
+
+    /// normalized key of a row, composite keys are joined by `COMPOSITE_KEY_SEPARATOR`
+    pub fn row_key(&self, row: &[String], key_indexes: &[usize]) -> anyhow::Result<String> {
+        let mut keys = Vec::with_capacity(key_indexes.len());
+        for index in key_indexes {
+            let data = &row[*index];
+            let key = match self.cell_type[*index] {
+                CellType::UInt => crate::parse!(data,u32).to_string(),
+                CellType::Int => crate::parse!(data,i32).to_string(),
+                CellType::Long => crate::parse!(data,i64).to_string(),
+                CellType::String => data.clone(),
+                ref other => {
+                    return Err(anyhow!(format!("{} key column {} type {} is not a scalar type", self.name, self.cell_name[*index], other)));
+                }
+            };
+            keys.push(key);
+        }
+        Ok(keys.join(COMPOSITE_KEY_SEPARATOR))
+    }
+
+    /// report non scalar key columns, empty keys and duplicated keys of every row
+    pub fn check_keys(&self, key_indexes: &[usize]) -> Vec<anyhow::Error> {
+        let mut errors = vec![];
+        for index in key_indexes {
+            let ty = &self.cell_type[*index];
+            if !matches!(ty, CellType::UInt | CellType::Int | CellType::Long | CellType::String) {
+                errors.push(self.column_error(*index, format!("key type {} is not a scalar type", ty)));
+            }
+        }
+        if !errors.is_empty() {
+            return errors;
+        }
+        let mut rows: HashMap<Vec<&String>, usize> = HashMap::with_capacity(self.data.len());
+        let mut joined_rows: HashMap<String, usize> = HashMap::with_capacity(self.data.len());
+        for (i, row) in self.data.iter().enumerate() {
+            let mut key = Vec::with_capacity(key_indexes.len());
+            for index in key_indexes {
+                let data = &row[*index];
+                if data.is_empty() {
+                    errors.push(self.cell_error(i, *index, "key is empty"));
+                }
+                key.push(data);
+            }
+            if key.iter().any(|k| { k.is_empty() }) {
+                continue;
+            }
+            let joined = match self.row_key(row, key_indexes) {
+                Ok(joined) => joined,
+                // keys that cannot be parsed are already reported by the type check
+                Err(_) => continue,
+            };
+            if let Some(first) = rows.insert(key, i) {
+                errors.push(self.cell_error(i, key_indexes[0], format!("duplicate key: {}, first defined at row {}", joined, self.row_number_of(first))));
+            } else if let Some(first) = joined_rows.get(&joined) {
+                errors.push(self.cell_error(i, key_indexes[0], format!("composite key: {} is ambiguous with row {}", joined, self.row_number_of(*first))));
+            }
+            joined_rows.entry(joined).or_insert(i);
+        }
+        errors
+    }
 }
 
 impl ToLua for GameConfig {
     type Output = String;
 
     fn to_lua(&self) -> anyhow::Result<Self::Output> {
-        // todo key all
-        let (key_index, _) = self.key_type.iter().enumerate().find(|(_, key)| { **key == AllKey || **key == ServerKey || **key == ClientKey || **key == All }).ok_or(anyhow!(format!("{} allkey|serverkey|clientkey not found",self.name)))?;
-        let mut key_to_index = Vec::with_capacity(self.data.len());
-        let mut formatted_rows = vec![];
+        self.write_lua(LuaOptions::default())
+    }
+}
+
+impl GameConfig {
+    /// a lua chunk returning the config as a read only table, names and strings are escaped as lua strings
+    pub fn write_lua(&self, options: LuaOptions) -> anyhow::Result<String> {
+        let key_indexes = self.lua_key_indexes()?;
+        let key_errors = self.check_keys(&key_indexes);
+        if !key_errors.is_empty() {
+            let report = key_errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n");
+            return Err(anyhow!(format!("{} key check failed:\n{}", self.name, report)));
+        }
+        let mut formatted_cells = Vec::with_capacity(self.data.len());
         for (i, row_data) in self.data.iter().enumerate() {
             let mut formatted_one_cell = vec![];
-            for (i, (cell_data, ty)) in row_data.iter().zip(&self.cell_type).enumerate() {
-                let formatted_cell_data = LuaWriter::write(ty, cell_data)?;
-                formatted_one_cell.push(format!("[{}] = {}", i + 1, formatted_cell_data));
-                if i == key_index {
-                    key_to_index.push((formatted_cell_data, i + 1));
-                }
+            for (j, (cell_data, ty)) in row_data.iter().zip(&self.cell_type).enumerate() {
+                let formatted_cell_data = if self.is_null(i, j) { "nil".to_string() } else { LuaWriter::write_at(ty, cell_data, &self.cell_location(i, j))? };
+                formatted_one_cell.push(formatted_cell_data);
             }
+            formatted_cells.push(formatted_one_cell);
+        }
+        let shared = if options.compact { SharedTables::new(formatted_cells.iter().flatten()) } else { SharedTables::default() };
+        let mut key_to_index = Vec::with_capacity(self.data.len());
+        let mut formatted_rows = vec![];
+        for (i, (row_data, formatted_one_cell)) in self.data.iter().zip(formatted_cells).enumerate() {
+            let formatted_one_cell = formatted_one_cell.into_iter().enumerate().map(|(j, cell)| { format!("[{}] = {}", j + 1, shared.reference(cell)) }).collect::<Vec<_>>();
+            let formatted_key = if key_indexes.len() == 1 {
+                LuaWriter::write(&self.cell_type[key_indexes[0]], &row_data[key_indexes[0]])?
+            } else {
+                self.row_key(row_data, &key_indexes)?.to_lua()?
+            };
+            key_to_index.push((formatted_key, i + 1));
             let formatted_one_row = format!("    [{}] = {{ {} }}", i + 1, formatted_one_cell.join(", "));
             formatted_rows.push(formatted_one_row);
         }
@@ -113,22 +715,22 @@ impl ToLua for GameConfig {
         let formatted_id_to_index = format!("{}", formatted_id_to_index.join(", "));
         let mut formatted_key_to_index = vec![];
         for (i, n) in self.cell_name.iter().enumerate() {
-            formatted_key_to_index.push(format!("['{}'] = {}", n, i + 1));
+            formatted_key_to_index.push(format!("[{}] = {}", lua_string(n), i + 1));
         }
         let formatted_key_to_index = format!("{}", formatted_key_to_index.join(", "));
         Ok(format!(r#"
-local data = {{
+{}local data = {{
 {}
 }}
 
-local s_name = '{}'
+local s_name = {}
 
 local s_id = {{ {} }}
 
 local s_key = {{ {} }}
 
 {}
-        "#, formatted_config, self.name, formatted_id_to_index, formatted_key_to_index, lua_meta_table()).trim().to_string())
+        "#, shared.declare(), formatted_config, lua_string(&self.name), formatted_id_to_index, formatted_key_to_index, lua_meta_table()).trim().to_string())
     }
 }
 
@@ -195,4 +797,48 @@ end
 
 return config
     "#.trim().to_string()
+}
+#[cfg(test)]
+mod test {
+    use crate::excel::convert::ToLua;
+    use crate::excel::excel_define::{CellType, ExportSide, GameConfig, KeyType};
+
+    fn config(key_type: Vec<KeyType>, data: Vec<Vec<&str>>) -> GameConfig {
+        GameConfig::builder()
+            .name("level".to_string())
+            .cell_name(vec!["id".to_string(), "level".to_string(), "exp".to_string()])
+            .key_type(key_type)
+            .cell_type(vec![CellType::UInt, CellType::UInt, CellType::UInt])
+            .data(data.into_iter().map(|row| { row.into_iter().map(ToString::to_string).collect() }).collect())
+            .build()
+    }
+
+    #[test]
+    fn test_check_keys() -> anyhow::Result<()> {
+        let single = config(vec![KeyType::AllKey, KeyType::All, KeyType::All], vec![vec!["1", "1", "10"], vec!["", "2", "20"], vec!["1", "3", "30"]]);
+        let errors = single.check_keys(&single.lua_key_indexes()?).iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors, vec![r#" [level] A7 id(uint) data: "": key is empty"#, r#" [level] A8 id(uint) data: "1": duplicate key: 1, first defined at row 6"#]);
+        assert!(single.to_lua().is_err());
+
+        let composite = config(vec![KeyType::AllKey, KeyType::AllKey, KeyType::All], vec![vec!["1", "1", "10"], vec!["1", "2", "20"]]);
+        let key_indexes = composite.lua_key_indexes()?;
+        assert_eq!(key_indexes, vec![0, 1]);
+        assert!(composite.check_keys(&key_indexes).is_empty());
+        assert!(composite.to_lua()?.contains(r#"local s_id = { ["1_1"] = 1, ["1_2"] = 2 }"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_side() -> anyhow::Result<()> {
+        let all = config(vec![KeyType::ServerKey, KeyType::ClientKey, KeyType::Server], vec![vec!["1", "2", "10"]]);
+        let server = all.for_side(ExportSide::Server);
+        assert_eq!(server.cell_name, vec!["id", "exp"]);
+        assert_eq!(server.data, vec![vec!["1", "10"]]);
+        assert_eq!(server.lua_key_indexes()?, vec![0]);
+        let client = all.for_side(ExportSide::Client);
+        assert_eq!(client.cell_name, vec!["level"]);
+        assert_eq!(client.lua_key_indexes()?, vec![0]);
+        assert!(client.to_lua()?.contains("local s_id = { [2] = 1 }"));
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use chrono::FixedOffset;
+use rayon::prelude::*;
+use tracing::info;
+use walkdir::WalkDir;
+
+use crate::excel::checker::{check_game_config, check_references};
+use crate::excel::enum_define::{ENUM_SHEET, EnumDefines};
+use crate::excel::excel_define::{ExportSide, GameConfig, GameConfigs};
+use crate::excel::lang_define::{LANG_SHEET, LangTexts, parse_language};
+use crate::excel::report::{ErrorReport, ExcelError};
+use crate::excel::time_define;
+
+/// options of the steps between reading the workbooks and writing the outputs
+#[derive(typed_builder::TypedBuilder, Debug)]
+pub struct ExportOptions {
+    /// also resolve enum and flags types against the enums of the .proto files in this dir
+    #[builder(default)]
+    pub proto_enum_path: Option<String>,
+    /// member of the proto Language enum the lang cells are written in, the default member if not given
+    #[builder(default)]
+    pub source_language: Option<String>,
+    /// server timezone of datetime cells without an offset
+    pub timezone: FixedOffset,
+    /// sides the configs are exported to, references are checked in the bundle of every side
+    #[builder(default = vec![ExportSide::Server])]
+    pub sides: Vec<ExportSide>,
+}
+
+/// enums and translations defined by the enum and lang sheets of an export
+pub struct ExportDefines {
+    pub enums: EnumDefines,
+    pub lang: LangTexts,
+}
+
+/// turn the sheets as read from the workbooks into the exported configs: the enum and lang sheets are taken out,
+/// defaults applied, enums, datetimes and lang cells resolved, every error found on the way goes to `report`
+pub fn prepare_configs(game_configs: &mut GameConfigs, options: &ExportOptions, report: &mut ErrorReport) -> anyhow::Result<ExportDefines> {
+    game_configs.data.sort_by(|a, b| { a.name.cmp(&b.name) });
+    // enum and lang sheets only define enums and translations, they are not exported as configs
+    let (enum_sheets, data): (Vec<_>, Vec<_>) = std::mem::take(&mut game_configs.data).into_iter().partition(|config| { config.name == ENUM_SHEET });
+    let (lang_sheets, data): (Vec<_>, Vec<_>) = data.into_iter().partition(|config| { config.name == LANG_SHEET });
+    game_configs.data = data;
+    let enums = read_enum_defines(&enum_sheets, options, report);
+    let mut lang = read_lang_texts(&lang_sheets, options, report)?;
+    report.extend(check_config_name(game_configs));
+    // column rules are written for the workbook text, so they are checked before enum, datetime and lang cells are resolved
+    report.extend(check_data_type(game_configs));
+    for config in &mut game_configs.data {
+        config.apply_defaults();
+    }
+    // a column of a side can only reference columns that are in the bundle of the same side
+    for side in &options.sides {
+        report.extend(check_references(&game_configs.for_side(*side)));
+    }
+    for config in &mut game_configs.data {
+        report.extend(enums.resolve_config(config));
+    }
+    for config in &mut game_configs.data {
+        report.extend(time_define::resolve_config(config, &options.timezone));
+    }
+    for config in &mut game_configs.data {
+        lang.extract_config(config);
+    }
+    Ok(ExportDefines { enums, lang })
+}
+
+/// every sheet becomes a config named after the sheet, so the names must be unique across all workbooks
+fn check_config_name(config: &GameConfigs) -> Vec<anyhow::Error> {
+    let mut errors = vec![];
+    let mut sources: HashMap<&str, &str> = HashMap::new();
+    for config in &config.data {
+        let source = sources.entry(&config.name).or_insert(&config.source);
+        if *source != config.source {
+            errors.push(ExcelError::sheet(&config.source, &config.name, format!("duplicate config name: {}, first defined in: {}", config.name, source)).into());
+        }
+    }
+    errors
+}
+
+fn check_data_type(config: &GameConfigs) -> Vec<anyhow::Error> {
+    config.data.par_iter().map(check_game_config).collect::<Vec<_>>().into_iter().flatten().collect()
+}
+
+/// enums of the enum sheets and of the .proto files in proto_enum_path
+fn read_enum_defines(enum_sheets: &[GameConfig], options: &ExportOptions, report: &mut ErrorReport) -> EnumDefines {
+    let mut enums = EnumDefines::default();
+    if let Some(proto_enum_path) = &options.proto_enum_path {
+        for entry in WalkDir::new(proto_enum_path) {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() && entry.path().extension().map(|ext| { ext == "proto" }).unwrap_or(false) => {
+                    if let Err(error) = enums.add_proto(entry.path()) {
+                        report.push(ExcelError::sheet(&entry.path().display().to_string(), "", format!("{:#}", error)).into());
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    report.push(ExcelError::sheet(proto_enum_path, "", error.to_string()).into());
+                }
+            }
+        }
+    }
+    for sheet in enum_sheets {
+        report.extend(check_game_config(sheet));
+        report.extend(enums.add_sheet(sheet));
+    }
+    info!("{} enums defined", enums.enums.len());
+    enums
+}
+
+/// translations of the lang sheets, the lang cells are collected by `LangTexts::extract_config`
+fn read_lang_texts(lang_sheets: &[GameConfig], options: &ExportOptions, report: &mut ErrorReport) -> anyhow::Result<LangTexts> {
+    let source_language = match &options.source_language {
+        Some(name) => parse_language(name)?,
+        None => Default::default(),
+    };
+    let mut lang = LangTexts::new(source_language);
+    for sheet in lang_sheets {
+        report.extend(check_game_config(sheet));
+        report.extend(lang.add_sheet(sheet));
+    }
+    info!("source language: {}, {} translated languages", source_language.as_str_name(), lang.translations.len());
+    Ok(lang)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::FixedOffset;
+
+    use crate::excel::excel_define::{CellRef, CellRule, CellType, ExportSide, GameConfig, GameConfigs, KeyType};
+    use crate::excel::export::{ExportOptions, prepare_configs};
+    use crate::excel::report::ErrorReport;
+
+    #[test]
+    fn test_prepare_configs() -> anyhow::Result<()> {
+        let enum_sheet = GameConfig::builder()
+            .name("enum".to_string())
+            .cell_name(vec!["enum".to_string(), "name".to_string(), "value".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::AllKey, KeyType::All])
+            .cell_type(vec![CellType::String, CellType::String, CellType::Int])
+            .data(vec![vec!["Quality".to_string(), "Epic".to_string(), "3".to_string()]])
+            .build();
+        let item = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "quality".to_string(), "count".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::Enum("Quality".to_string()), CellType::UInt])
+            .cell_rule(vec![vec![], vec![], CellRule::parse_rules("default:1")?])
+            .data(vec![vec!["1".to_string(), "Epic".to_string(), "".to_string()]])
+            .build();
+        let mut game_configs = GameConfigs::builder().commit_id(String::new()).create_mills(0).data(vec![item, enum_sheet]).build();
+        let options = ExportOptions::builder().timezone(FixedOffset::east_opt(0).unwrap()).build();
+        let mut report = ErrorReport::default();
+        let defines = prepare_configs(&mut game_configs, &options, &mut report)?;
+        assert!(report.is_empty());
+        assert_eq!(defines.enums.enums.len(), 1);
+        assert_eq!(game_configs.data.len(), 1);
+        assert_eq!(game_configs.data[0].data, vec![vec!["1".to_string(), "3".to_string(), "1".to_string()]]);
+
+        let item = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "icon".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::Client])
+            .cell_type(vec![CellType::UInt, CellType::String])
+            .data(vec![vec!["1".to_string(), "a.png".to_string()]])
+            .build();
+        let shop = GameConfig::builder()
+            .name("shop".to_string())
+            .cell_name(vec!["id".to_string(), "icon".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::Server])
+            .cell_type(vec![CellType::UInt, CellType::String])
+            .cell_ref(vec![None, Some("ref:item.icon".parse::<CellRef>()?)])
+            .data(vec![vec!["1".to_string(), "a.png".to_string()]])
+            .build();
+        let mut game_configs = GameConfigs::builder().commit_id(String::new()).create_mills(0).data(vec![item, shop]).build();
+        let options = ExportOptions::builder().timezone(FixedOffset::east_opt(0).unwrap()).sides(vec![ExportSide::Server, ExportSide::Client]).build();
+        let mut report = ErrorReport::default();
+        prepare_configs(&mut game_configs, &options, &mut report)?;
+        assert_eq!(report.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use proto::proto::Language;
+
+use crate::excel::composite::CompositeValue;
+use crate::excel::convert::ToLua;
+use crate::excel::excel_define::{CellType, GameConfig, GameConfigs};
+
+/// sheets with this name hold translations, a `key` column and one column per target language named after a
+/// member of the proto `Language` enum, e.g. `En`
+pub const LANG_SHEET: &str = "lang";
+
+/// dir of the language packs, `{Language}.json` in the output dir and `{Language}.lua` in the lua dir
+pub const LANG_DIR: &str = "lang";
+
+pub fn parse_language(name: &str) -> anyhow::Result<Language> {
+    Language::from_str_name(name.trim()).ok_or_else(|| { anyhow!(format!("unknown language: {}, expect a member of the proto Language enum", name)) })
+}
+
+/// key a lang cell is exported as, `{sheet}.{column}.{row key}`, e.g. `item.name.1001`
+pub fn lang_key(config: &GameConfig, row_key: &str, column: usize) -> String {
+    format!("{}.{}.{}", config.name, config.cell_name[column], row_key)
+}
+
+/// keys of the lang values of a cell, the cell itself for a `lang` column and the nested values of an array or struct
+fn cell_lang_keys(ty: &CellType, data: &str) -> Vec<String> {
+    if data.is_empty() {
+        return vec![];
+    }
+    if !ty.is_composite() {
+        return vec![data.to_string()];
+    }
+    match CompositeValue::parse(ty, data) {
+        Ok(mut value) => value.lang_texts(ty).into_iter().map(|(_, key)| { key.clone() }).filter(|key| { !key.is_empty() }).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// texts of one language
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct LangPack {
+    /// name of the `Language` member
+    pub language: String,
+    /// texts of the source language are written in the lang cells, the other packs are translations
+    pub source: bool,
+    pub texts: BTreeMap<String, String>,
+}
+
+/// source texts collected from the lang cells and the translations of the lang sheets
+#[derive(Debug, Clone)]
+pub struct LangTexts {
+    pub source_language: Language,
+    pub source: BTreeMap<String, String>,
+    pub translations: BTreeMap<Language, BTreeMap<String, String>>,
+    /// workbook every translated key is defined in
+    translated_in: HashMap<String, String>,
+}
+
+impl LangTexts {
+    pub fn new(source_language: Language) -> Self {
+        Self {
+            source_language,
+            source: BTreeMap::new(),
+            translations: BTreeMap::new(),
+            translated_in: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.source.is_empty() && self.translations.is_empty()
+    }
+
+    /// one key per row, empty translations are treated as missing
+    pub fn add_sheet(&mut self, config: &GameConfig) -> Vec<anyhow::Error> {
+        let key_index = match config.column_index("key", &CellType::String) {
+            Ok(key_index) => key_index,
+            Err(error) => return vec![config.sheet_error(error)],
+        };
+        let mut errors = vec![];
+        let mut languages = vec![];
+        for (j, name) in config.cell_name.iter().enumerate() {
+            if j == key_index {
+                continue;
+            }
+            if !matches!(config.cell_type[j], CellType::String | CellType::Lang) {
+                errors.push(config.column_error(j, "translation columns must be string"));
+                continue;
+            }
+            match parse_language(name) {
+                Ok(language) if language == self.source_language => {
+                    errors.push(config.column_error(j, format!("{} is the source language, its texts are written in the lang cells", name)));
+                }
+                Ok(language) => languages.push((j, language)),
+                Err(error) => errors.push(config.column_error(j, error)),
+            }
+        }
+        for (i, row) in config.data.iter().enumerate() {
+            let key = row[key_index].trim();
+            if key.is_empty() {
+                errors.push(config.cell_error(i, key_index, "lang key is empty"));
+                continue;
+            }
+            if let Some(source) = self.translated_in.get(key) {
+                errors.push(config.cell_error(i, key_index, format!("duplicate lang key, first defined in {}", source)));
+                continue;
+            }
+            self.translated_in.insert(key.to_string(), config.source.clone());
+            for (j, language) in &languages {
+                if !row[*j].is_empty() {
+                    self.translations.entry(*language).or_default().insert(key.to_string(), row[*j].clone());
+                }
+            }
+        }
+        errors
+    }
+
+    /// collect the source texts of the lang cells and replace them with their keys, lang values nested in an array or
+    /// struct get the key of the cell followed by their path, e.g. `item.rewards.1001.0.name`
+    pub fn extract_config(&mut self, config: &mut GameConfig) {
+        let lang_columns = config.cell_type.iter().enumerate().filter(|(_, ty)| { ty.has_lang() }).map(|(j, _)| { j }).collect::<Vec<_>>();
+        // a config without key columns is reported by the key check
+        let key_indexes = match config.lua_key_indexes() {
+            Ok(key_indexes) if !lang_columns.is_empty() => key_indexes,
+            _ => return,
+        };
+        for i in 0..config.data.len() {
+            // keys that cannot be parsed are already reported by the type check
+            let row_key = match config.row_key(&config.data[i], &key_indexes) {
+                Ok(row_key) => row_key,
+                Err(_) => continue,
+            };
+            for j in &lang_columns {
+                if config.data[i][*j].is_empty() {
+                    continue;
+                }
+                // rows with the same key are reported by the key check
+                let key = lang_key(config, &row_key, *j);
+                let ty = &config.cell_type[*j];
+                if !ty.is_composite() {
+                    let text = std::mem::replace(&mut config.data[i][*j], key.clone());
+                    self.source.insert(key, text);
+                    continue;
+                }
+                // cells that cannot be parsed are already reported by the type check
+                let mut value = match CompositeValue::parse(ty, &config.data[i][*j]) {
+                    Ok(value) => value,
+                    Err(_) => continue,
+                };
+                for (path, text) in value.lang_texts(ty) {
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let nested_key = format!("{}.{}", key, path);
+                    self.source.insert(nested_key.clone(), std::mem::replace(text, nested_key));
+                }
+                config.data[i][*j] = value.to_cell();
+            }
+        }
+    }
+
+    /// keys of the source texts without a translation, per target language
+    pub fn missing(&self) -> BTreeMap<Language, Vec<String>> {
+        let mut missing = BTreeMap::new();
+        for (language, texts) in &self.translations {
+            let keys = self.source.keys().filter(|key| { !texts.contains_key(*key) }).cloned().collect::<Vec<_>>();
+            if !keys.is_empty() {
+                missing.insert(*language, keys);
+            }
+        }
+        missing
+    }
+
+    /// translated keys that no lang cell is exported as anymore, with the workbook they are defined in
+    pub fn unused(&self) -> Vec<(String, String)> {
+        let mut unused = self.translated_in.iter().filter(|(key, _)| { !self.source.contains_key(*key) }).map(|(key, source)| { (key.clone(), source.clone()) }).collect::<Vec<_>>();
+        unused.sort();
+        unused
+    }
+
+    /// one pack per language holding only the keys of the bundle, the source pack first
+    pub fn packs(&self, bundle: &GameConfigs) -> Vec<LangPack> {
+        let mut keys = BTreeSet::new();
+        for config in &bundle.data {
+            for (j, ty) in config.cell_type.iter().enumerate() {
+                if ty.has_lang() {
+                    keys.extend(config.data.iter().flat_map(|row| { cell_lang_keys(ty, &row[j]) }));
+                }
+            }
+        }
+        let pick = |texts: &BTreeMap<String, String>| -> BTreeMap<String, String> {
+            keys.iter().filter_map(|key| { texts.get(key).map(|text| { (key.clone(), text.clone()) }) }).collect()
+        };
+        let mut packs = vec![LangPack { language: self.source_language.as_str_name().to_string(), source: true, texts: pick(&self.source) }];
+        for (language, texts) in &self.translations {
+            packs.push(LangPack { language: language.as_str_name().to_string(), source: false, texts: pick(texts) });
+        }
+        packs
+    }
+}
+
+/// a read only table of `{ key = text }`, loaded like a config named `lang_{Language}`
+impl ToLua for LangPack {
+    type Output = String;
+
+    fn to_lua(&self) -> anyhow::Result<Self::Output> {
+        let language = parse_language(&self.language)?;
+        let mut formatted_texts = vec![];
+        for (key, text) in &self.texts {
+            formatted_texts.push(format!("    [{}] = {}", key.to_lua()?, text.to_lua()?));
+        }
+        Ok(format!(r#"
+local texts = {{
+{}
+}}
+
+local config = {{ name = '{}_{}', language = {}, source = {} }}
+
+setmetatable(config, {{
+    __index = texts,
+    __newindex = function()
+        error("Attempt to modify read-only table")
+    end
+}})
+
+return config
+        "#, formatted_texts.join(",\n"), LANG_SHEET, self.language, language as i32, self.source).trim().to_string())
+    }
+}
+
+/// language packs loaded at runtime, a missing translation falls back to the source language
+#[derive(Debug, Clone, Default)]
+pub struct LangPacks {
+    source: Option<Language>,
+    packs: HashMap<Language, HashMap<String, String>>,
+}
+
+impl LangPacks {
+    /// every `{Language}.json` pack written by excel_tool to the `LANG_DIR` of the output dir
+    pub fn load<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let mut packs = LangPacks::default();
+        for entry in std::fs::read_dir(dir).context(format!("failed to read lang dir: {}", dir.display()))? {
+            let path = entry?.path();
+            if path.extension().map(|ext| { ext == "json" }).unwrap_or(false) {
+                let content = std::fs::read_to_string(&path).context(format!("failed to read: {}", path.display()))?;
+                let pack: LangPack = serde_json::from_str(&content).context(format!("incorrect lang pack: {}", path.display()))?;
+                packs.insert(pack)?;
+            }
+        }
+        Ok(packs)
+    }
+
+    pub fn insert(&mut self, pack: LangPack) -> anyhow::Result<()> {
+        let language = parse_language(&pack.language)?;
+        if pack.source {
+            if let Some(source) = self.source.filter(|source| { *source != language }) {
+                return Err(anyhow!(format!("both {} and {} are source language packs", source.as_str_name(), pack.language)));
+            }
+            self.source = Some(language);
+        }
+        self.packs.insert(language, pack.texts.into_iter().collect());
+        Ok(())
+    }
+
+    pub fn languages(&self) -> Vec<Language> {
+        let mut languages = self.packs.keys().copied().collect::<Vec<_>>();
+        languages.sort();
+        languages
+    }
+
+    /// text of a lang key in the language, or in the source language if it is not translated
+    pub fn get(&self, language: Language, key: &str) -> Option<&str> {
+        let translated = self.packs.get(&language).and_then(|texts| { texts.get(key) });
+        let source = || { self.source.and_then(|source| { self.packs.get(&source) }).and_then(|texts| { texts.get(key) }) };
+        translated.or_else(source).map(String::as_str)
+    }
+
+    /// like `get` for the `Language` value of a player, e.g. `LoginRequest.language`, unknown values use the source language
+    pub fn get_by_value(&self, language: i32, key: &str) -> Option<&str> {
+        match Language::from_i32(language) {
+            Some(language) => self.get(language, key),
+            None => self.source.and_then(|source| { self.get(source, key) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proto::proto::Language;
+
+    use crate::excel::convert::ToLua;
+    use crate::excel::excel_define::{CellType, GameConfig, GameConfigs, KeyType};
+    use crate::excel::lang_define::{LangPacks, LangTexts};
+
+    #[test]
+    fn test_lang_texts() -> anyhow::Result<()> {
+        let source = Language::default();
+        let target = if source == Language::En { Language::Zh } else { Language::En };
+        let mut item = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "name".to_string(), "desc".to_string(), "skills".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::Server, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::Lang, CellType::Lang, CellType::parse_type("array<struct{id:uint,name:lang}>")?])
+            .data(vec![
+                vec!["1001".to_string(), "sword".to_string(), "sharp".to_string(), "{1,slash},{2}".to_string()],
+                vec!["1002".to_string(), "shield".to_string(), "".to_string(), "".to_string()],
+            ])
+            .build();
+        let lang = GameConfig::builder()
+            .name("lang".to_string())
+            .cell_name(vec!["key".to_string(), target.as_str_name().to_string(), "Klingon".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::String, CellType::String, CellType::String])
+            .data(vec![
+                vec!["item.name.1001".to_string(), "translated sword".to_string(), "".to_string()],
+                vec!["item.name.1003".to_string(), "removed".to_string(), "".to_string()],
+                vec!["item.name.1001".to_string(), "again".to_string(), "".to_string()],
+            ])
+            .source("lang.xlsx".to_string())
+            .build();
+        let mut texts = LangTexts::new(source);
+        let errors = texts.add_sheet(&lang).iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("unknown language: Klingon"));
+        assert!(errors[1].ends_with("duplicate lang key, first defined in lang.xlsx"));
+        texts.extract_config(&mut item);
+        assert_eq!(item.data[0], vec!["1001", "item.name.1001", "item.desc.1001", r#"[{1,"item.skills.1001.0.name"},{2,""}]"#]);
+        assert_eq!(item.data[1][2], "");
+        assert_eq!(item.data[1][3], "");
+        assert_eq!(texts.missing()[&target], vec!["item.desc.1001", "item.name.1002", "item.skills.1001.0.name"]);
+        assert_eq!(texts.unused(), vec![("item.name.1003".to_string(), "lang.xlsx".to_string())]);
+
+        let bundle = GameConfigs { data: vec![item], ..Default::default() };
+        let client = bundle.for_side(crate::excel::excel_define::ExportSide::Client);
+        let packs = texts.packs(&client);
+        assert_eq!(packs.len(), 2);
+        assert_eq!(packs[0].texts.keys().collect::<Vec<_>>(), vec!["item.name.1001", "item.name.1002", "item.skills.1001.0.name"]);
+        assert_eq!(packs[0].texts["item.skills.1001.0.name"], "slash");
+        assert!(packs[1].to_lua()?.contains(r#"    ["item.name.1001"] = "translated sword""#));
+
+        let mut runtime = LangPacks::default();
+        for pack in texts.packs(&bundle) {
+            runtime.insert(pack)?;
+        }
+        assert_eq!(runtime.get(target, "item.name.1001"), Some("translated sword"));
+        assert_eq!(runtime.get(target, "item.name.1002"), Some("shield"));
+        assert_eq!(runtime.get_by_value(-1, "item.desc.1001"), Some("sharp"));
+        assert_eq!(runtime.get(target, "item.name.1003"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// how `GameConfig` is written as lua
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LuaOptions {
+    /// tables written more than once in a sheet are created once and shared by the rows, see `SharedTables`
+    pub compact: bool,
+}
+
+/// a double quoted lua string, control characters are written as `\ddd` so a following digit is not read as part of it
+pub fn lua_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\{:03}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// shortest representation that reads back to the same value, nan and infinity are written as divisions by zero
+pub fn lua_float<T: Display + Into<f64> + Copy>(value: T) -> String {
+    let number: f64 = value.into();
+    if number.is_nan() {
+        "(0/0)".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "(1/0)" } else { "(-1/0)" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// a line comment, line breaks would end it
+pub fn lua_comment(text: &str) -> String {
+    format!("--- {}", text.replace(['\r', '\n'], " "))
+}
+
+/// tables written more than once in a sheet, they are put in `s_shared` and the cells refer to them by index
+///
+/// the rows share these tables, so modifying one at runtime changes every row using it
+#[derive(Debug, Default)]
+pub struct SharedTables {
+    tables: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl SharedTables {
+    pub fn new<'a>(cells: impl IntoIterator<Item=&'a String>) -> Self {
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        let mut tables = vec![];
+        for cell in cells {
+            if !cell.starts_with('{') {
+                continue;
+            }
+            let count = counts.entry(cell).or_default();
+            *count += 1;
+            if *count == 2 {
+                tables.push(cell.clone());
+            }
+        }
+        let index = tables.iter().enumerate().map(|(i, table)| { (table.clone(), i + 1) }).collect();
+        SharedTables { tables, index }
+    }
+
+    /// `s_shared[n]` if the cell is shared, otherwise the cell itself
+    pub fn reference(&self, cell: String) -> String {
+        match self.index.get(&cell) {
+            None => cell,
+            Some(i) => format!("s_shared[{}]", i),
+        }
+    }
+
+    /// declaration of `s_shared`, nothing if no table is shared
+    pub fn declare(&self) -> String {
+        if self.tables.is_empty() {
+            return String::new();
+        }
+        let formatted_tables = self.tables.iter().enumerate().map(|(i, table)| { format!("    [{}] = {}", i + 1, table) }).collect::<Vec<_>>();
+        format!("local s_shared = {{\n{}\n}}\n\n", formatted_tables.join(",\n"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::excel::convert::ToLua;
+    use crate::excel::excel_define::{CellType, GameConfig, KeyType};
+    use crate::excel::lua_emitter::{lua_float, lua_string, LuaOptions};
+
+    #[test]
+    fn test_lua_emitter() -> anyhow::Result<()> {
+        let text = "a\"b\\c\r\nd\te\u{0}1\u{7f}中文";
+        assert_eq!(lua_string(text), "\"a\\\"b\\\\c\\r\\nd\\te\\0001\\127中文\"");
+        assert_eq!(lua_float(1.5f32), "1.5");
+        assert_eq!(lua_float(0.1f32), "0.1");
+        assert_eq!(lua_float(f64::NAN), "(0/0)");
+        assert_eq!(lua_float(f32::NEG_INFINITY), "(-1/0)");
+        assert_eq!(lua_float(1e-7f64), "0.0000001");
+
+        let config = GameConfig::builder()
+            .name("it'em".to_string())
+            .cell_name(vec!["id".to_string(), "pos".to_string(), "na\"me".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::Vector2Int, CellType::String])
+            .data(vec![
+                vec!["1".to_string(), "1,2".to_string(), "a'".to_string()],
+                vec!["2".to_string(), "1,2".to_string(), "b".to_string()],
+                vec!["3".to_string(), "3,4".to_string(), "c".to_string()],
+            ])
+            .build();
+        let code = config.to_lua()?;
+        assert!(code.contains(r#"local s_name = "it'em""#));
+        assert!(!code.contains("s_shared"));
+        assert!(code.contains(r#"local s_key = { ["id"] = 1, ["pos"] = 2, ["na\"me"] = 3 }"#));
+        let compact = config.write_lua(LuaOptions { compact: true })?;
+        assert!(compact.contains("local s_shared = {\n    [1] = { 1, 2 }\n}"));
+        assert!(compact.contains("[1] = { [1] = 1, [2] = s_shared[1], [3] = \"a'\" }"));
+        assert!(compact.contains("[3] = { [1] = 3, [2] = { 3, 4 }, [3] = \"c\" }"));
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::Context;
+use tracing::warn;
+
+use crate::excel::excel_define::GameConfig;
+use crate::excel::revision::sha256_hex;
+
+pub const MANIFEST_FILE: &str = "excel_manifest.bytes";
+/// format of the generated lua, rust, json and proto files, it is part of the manifest options,
+/// bump it whenever a writer changes its output so unchanged workbooks get their files rewritten too
+pub const OUTPUT_FORMAT_VERSION: u32 = 1;
+
+/// parsed configs of one workbook, keyed by the content hash of the workbook file
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct WorkbookEntry {
+    pub hash: String,
+    pub configs: Vec<CachedConfig>,
+}
+
+/// `GameConfig` skips its source locations when serialized, they are kept here so cached configs still report errors precisely
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CachedConfig {
+    pub config: GameConfig,
+    pub row_number: Vec<usize>,
+    pub column_number: Vec<usize>,
+}
+
+/// what the previous export produced, used to skip workbooks that did not change
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct ExportManifest {
+    /// export options that change the parsed data or the generated files, a different value invalidates every workbook
+    pub options: String,
+    pub workbooks: BTreeMap<String, WorkbookEntry>,
+}
+
+impl ExportManifest {
+    pub fn new(options: String) -> Self {
+        Self {
+            options,
+            workbooks: Default::default(),
+        }
+    }
+
+    /// a missing, unreadable or outdated manifest is treated as empty so everything is exported again
+    pub fn load<P: AsRef<Path>>(path: P, options: &str) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::new(options.to_string());
+        }
+        let manifest = std::fs::read(path).context(format!("failed to read manifest: {}", path.display())).and_then(|bytes| {
+            bincode::deserialize::<ExportManifest>(&bytes).context(format!("failed to decode manifest: {}", path.display()))
+        });
+        match manifest {
+            Ok(manifest) if manifest.options == options => manifest,
+            Ok(_) => {
+                warn!("export options changed, ignore manifest: {}", path.display());
+                Self::new(options.to_string())
+            }
+            Err(error) => {
+                warn!("{:#}, ignore it", error);
+                Self::new(options.to_string())
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let encoded = bincode::serialize(self).context("failed to serialize manifest")?;
+        std::fs::write(path, encoded).context(format!("failed to write manifest: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// configs of an unchanged workbook, `None` if the workbook is new or its content changed
+    pub fn configs(&self, source: &str, hash: &str) -> Option<Vec<GameConfig>> {
+        let entry = self.workbooks.get(source)?;
+        if entry.hash != hash {
+            return None;
+        }
+        let configs = entry.configs.iter().cloned().map(|cached| {
+            GameConfig {
+                source: source.to_string(),
+                row_number: cached.row_number,
+                column_number: cached.column_number,
+                ..cached.config
+            }
+        }).collect();
+        Some(configs)
+    }
+
+    pub fn insert(&mut self, source: &str, hash: String, configs: &[GameConfig]) {
+        let configs = configs.iter().map(|config| {
+            CachedConfig {
+                config: config.clone(),
+                row_number: config.row_number.clone(),
+                column_number: config.column_number.clone(),
+            }
+        }).collect();
+        self.workbooks.insert(source.to_string(), WorkbookEntry { hash, configs });
+    }
+
+    pub fn config_names(&self) -> BTreeSet<String> {
+        self.workbooks.values().flat_map(|entry| { entry.configs.iter().map(|cached| { cached.config.name.clone() }) }).collect()
+    }
+}
+
+/// sha256 of the file content in hex
+pub fn content_hash<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).context(format!("failed to read: {}", path.display()))?;
+    Ok(sha256_hex(&bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use crate::excel::excel_define::{CellType, GameConfig, KeyType};
+    use crate::excel::manifest::{content_hash, ExportManifest};
+
+    #[test]
+    fn test_manifest() -> anyhow::Result<()> {
+        let workbook = env::temp_dir().join("test_manifest_item.xlsx");
+        std::fs::write(&workbook, "item")?;
+        let hash = content_hash(&workbook)?;
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string()])
+            .key_type(vec![KeyType::AllKey])
+            .cell_type(vec![CellType::UInt])
+            .data(vec![vec!["1".to_string()]])
+            .source("item.xlsx".to_string())
+            .row_number(vec![6])
+            .column_number(vec![2])
+            .build();
+        let mut manifest = ExportManifest::new("client=false".to_string());
+        manifest.insert("item.xlsx", hash.clone(), &[config]);
+        let path = env::temp_dir().join("test_manifest.bytes");
+        manifest.save(&path)?;
+
+        let manifest = ExportManifest::load(&path, "client=false");
+        let configs = manifest.configs("item.xlsx", &hash).unwrap();
+        assert_eq!(configs[0].row_number, vec![6]);
+        assert_eq!(configs[0].column_number, vec![2]);
+        assert_eq!(configs[0].source, "item.xlsx");
+        assert!(manifest.config_names().contains("item"));
+
+        std::fs::write(&workbook, "item changed")?;
+        assert!(manifest.configs("item.xlsx", &content_hash(&workbook)?).is_none());
+        assert!(ExportManifest::load(&path, "client=true").workbooks.is_empty());
+        Ok(())
+    }
+}
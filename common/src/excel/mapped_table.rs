@@ -0,0 +1,601 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use chrono::TimeZone;
+use memmap2::Mmap;
+
+use crate::excel::bundle::{schema_hash, write_atomic};
+use crate::excel::composite::parse_composite;
+use crate::excel::config_loader::CellValue;
+use crate::excel::convert::*;
+use crate::excel::excel_define::{CellType, GameConfig, GameConfigs};
+
+/// first bytes of every `config.tables`
+pub const TABLES_MAGIC: [u8; 4] = *b"SDCM";
+/// version of the file layout, bumped when the header, the index or the table regions change
+pub const TABLES_VERSION: u16 = 1;
+/// magic, version, schema hash, crc and index length
+pub const TABLES_HEADER_LEN: usize = 4 + 2 + 8 + 4 + 8;
+/// every cell takes 8 bytes, a number or the offset and length of its text in the string pool
+const CELL_LEN: usize = 8;
+/// offset and length of the key text, then the row
+const KEY_LEN: usize = 12;
+
+/// tables of `config.tables`, numbers are little endian and offsets are relative to the end of the index
+///
+/// ```text
+/// header | bincode TablesIndex | per table: cells, null bitmap, keys sorted by text | string pool
+/// ```
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct TablesIndex {
+    commit_id: String,
+    digest: String,
+    create_mills: u128,
+    strings: u64,
+    string_len: u64,
+    tables: Vec<TableIndex>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct TableIndex {
+    name: String,
+    cell_name: Vec<String>,
+    cell_type: Vec<CellType>,
+    key_indexes: Vec<usize>,
+    rows: u64,
+    /// row major cells
+    cells: u64,
+    /// one bit per cell, set for the empty cells of nullable columns
+    nulls: u64,
+    keys: u64,
+}
+
+/// scalars are stored in the cell itself, the other types keep their text and are parsed when read
+fn is_fixed(ty: &CellType) -> bool {
+    matches!(ty, CellType::UInt | CellType::Int | CellType::Long | CellType::Bool | CellType::Float | CellType::Double
+        | CellType::Enum(_) | CellType::Flags(_) | CellType::DateTime | CellType::Duration)
+}
+
+fn fixed_cell(ty: &CellType, data: &String) -> anyhow::Result<u64> {
+    let bits = match ty {
+        CellType::UInt => Parse::<u32>::parse(data)? as u64,
+        CellType::Int | CellType::Enum(_) | CellType::Flags(_) => Parse::<i32>::parse(data)? as i64 as u64,
+        CellType::Long => Parse::<i64>::parse(data)? as u64,
+        CellType::Bool => Parse::<bool>::parse(data)? as u64,
+        CellType::Float => Parse::<f32>::parse(data)?.to_bits() as u64,
+        CellType::Double => Parse::<f64>::parse(data)?.to_bits(),
+        CellType::DateTime => Parse::<DateTime>::parse(data)?.timestamp() as u64,
+        CellType::Duration => Parse::<Duration>::parse(data)?.as_secs(),
+        other => {
+            return Err(anyhow!(format!("{} is not stored in the cell", other)));
+        }
+    };
+    Ok(bits)
+}
+
+/// texts written once and referred to by offset, equal texts share the bytes
+#[derive(Default)]
+struct StringPool {
+    bytes: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringPool {
+    fn slot(&mut self, text: &str) -> anyhow::Result<[u8; 8]> {
+        let offset = match self.offsets.get(text) {
+            Some(offset) => *offset,
+            None => {
+                let offset = u32::try_from(self.bytes.len()).map_err(|_| { anyhow!("string pool is larger than 4GB") })?;
+                self.bytes.extend_from_slice(text.as_bytes());
+                self.offsets.insert(text.to_string(), offset);
+                offset
+            }
+        };
+        let len = u32::try_from(text.len()).map_err(|_| { anyhow!("text is larger than 4GB") })?;
+        let mut slot = [0u8; 8];
+        slot[..4].copy_from_slice(&offset.to_le_bytes());
+        slot[4..].copy_from_slice(&len.to_le_bytes());
+        Ok(slot)
+    }
+}
+
+/// the whole `config.tables`, the same configs with the same `commit_id` and `create_mills` always give the same bytes
+pub fn encode_tables(game_configs: &GameConfigs) -> anyhow::Result<Vec<u8>> {
+    let mut index = TablesIndex {
+        commit_id: game_configs.commit_id.clone(),
+        digest: game_configs.digest.clone(),
+        create_mills: game_configs.create_mills,
+        ..Default::default()
+    };
+    let mut data = vec![];
+    let mut pool = StringPool::default();
+    for config in &game_configs.data {
+        let table = encode_table(config, &mut data, &mut pool).context(format!("failed to encode table: {}", config.name))?;
+        index.tables.push(table);
+    }
+    index.strings = data.len() as u64;
+    index.string_len = pool.bytes.len() as u64;
+    data.extend_from_slice(&pool.bytes);
+    let index = bincode::serialize(&index).context("failed to serialize TablesIndex")?;
+    let mut bytes = Vec::with_capacity(TABLES_HEADER_LEN + index.len() + data.len());
+    bytes.extend_from_slice(&TABLES_MAGIC);
+    bytes.extend_from_slice(&TABLES_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&schema_hash().to_le_bytes());
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&index);
+    hasher.update(&data);
+    bytes.extend_from_slice(&hasher.finalize().to_le_bytes());
+    bytes.extend_from_slice(&(index.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&index);
+    bytes.extend_from_slice(&data);
+    Ok(bytes)
+}
+
+fn encode_table(config: &GameConfig, data: &mut Vec<u8>, pool: &mut StringPool) -> anyhow::Result<TableIndex> {
+    let columns = config.cell_name.len();
+    let key_indexes = config.side_key_indexes();
+    if key_indexes.is_empty() {
+        return Err(anyhow!(format!("{} {} key not found", config.name, config.side)));
+    }
+    let cells = data.len() as u64;
+    let mut nulls = vec![0u8; (config.data.len() * columns).div_ceil(8)];
+    for (i, row) in config.data.iter().enumerate() {
+        if row.len() != columns {
+            return Err(anyhow!(format!("{} row {} expect {} cells, got: {}", config.name, i + 1, columns, row.len())));
+        }
+        for (j, (cell_data, ty)) in row.iter().zip(&config.cell_type).enumerate() {
+            if config.is_null(i, j) {
+                let bit = i * columns + j;
+                nulls[bit / 8] |= 1 << (bit % 8);
+                data.extend_from_slice(&[0u8; CELL_LEN]);
+            } else if is_fixed(ty) {
+                let bits = fixed_cell(ty, cell_data).map_err(|e| { config.cell_error(i, j, e) })?;
+                data.extend_from_slice(&bits.to_le_bytes());
+            } else {
+                data.extend_from_slice(&pool.slot(cell_data)?);
+            }
+        }
+    }
+    let null_offset = data.len() as u64;
+    data.extend_from_slice(&nulls);
+    let mut keys = Vec::with_capacity(config.data.len());
+    for (i, row) in config.data.iter().enumerate() {
+        keys.push((config.row_key(row, &key_indexes)?, i as u32));
+    }
+    keys.sort();
+    for pair in keys.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            return Err(anyhow!(format!("{} duplicate key: {}", config.name, pair[0].0)));
+        }
+    }
+    let key_offset = data.len() as u64;
+    for (key, row) in &keys {
+        data.extend_from_slice(&pool.slot(key)?);
+        data.extend_from_slice(&row.to_le_bytes());
+    }
+    Ok(TableIndex {
+        name: config.name.clone(),
+        cell_name: config.cell_name.clone(),
+        cell_type: config.cell_type.clone(),
+        key_indexes,
+        rows: config.data.len() as u64,
+        cells,
+        nulls: null_offset,
+        keys: key_offset,
+    })
+}
+
+/// write `config.tables`, processes that mapped the previous file keep reading it until they open the new one
+pub fn write_tables(game_configs: &GameConfigs, path: &Path) -> anyhow::Result<()> {
+    let bytes = encode_tables(game_configs)?;
+    write_atomic(path, &bytes)
+}
+
+/// `config.tables` mapped into memory, processes mapping the same file share its pages
+///
+/// cells are read from the mapped bytes when they are accessed, texts are borrowed from the map
+#[derive(Debug)]
+pub struct MappedTables {
+    map: Mmap,
+    data: usize,
+    index: TablesIndex,
+    tables: HashMap<String, usize>,
+    columns: Vec<HashMap<String, usize>>,
+}
+
+impl MappedTables {
+    /// only the header and the index are checked, the cells are not read until they are accessed, see `verify`
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<MappedTables> {
+        let path = path.as_ref();
+        let file = File::open(path).context(format!("failed to open file: {}", path.display()))?;
+        // SAFETY: excel_tool replaces the file by rename instead of writing it in place, so the mapped bytes do not change
+        let map = unsafe { Mmap::map(&file) }.context(format!("failed to map file: {}", path.display()))?;
+        MappedTables::new(map).context(format!("failed to read: {}", path.display()))
+    }
+
+    fn new(map: Mmap) -> anyhow::Result<MappedTables> {
+        let bytes = &map[..];
+        if bytes.len() < TABLES_HEADER_LEN {
+            return Err(anyhow!(format!("not a config tables file, {} bytes is shorter than the header", bytes.len())));
+        }
+        if bytes[..4] != TABLES_MAGIC {
+            return Err(anyhow!(format!("not a config tables file, magic: {:?}", &bytes[..4])));
+        }
+        let version = u16::from_le_bytes(bytes[4..6].try_into()?);
+        if version != TABLES_VERSION {
+            return Err(anyhow!(format!("unsupported config tables version: {}, expect: {}", version, TABLES_VERSION)));
+        }
+        let hash = u64::from_le_bytes(bytes[6..14].try_into()?);
+        if hash != schema_hash() {
+            return Err(anyhow!(format!("config tables schema {:016x} is not compatible with {:016x}, export it with the excel_tool of this build", hash, schema_hash())));
+        }
+        let index_len = u64::from_le_bytes(bytes[18..26].try_into()?) as usize;
+        let data = TABLES_HEADER_LEN.checked_add(index_len).filter(|data| { *data <= bytes.len() }).ok_or(anyhow!(format!("config tables index length {} out of range", index_len)))?;
+        let index: TablesIndex = bincode::deserialize(&bytes[TABLES_HEADER_LEN..data]).context("failed to deserialize TablesIndex")?;
+        let data_len = (bytes.len() - data) as u64;
+        if index.strings.checked_add(index.string_len) != Some(data_len) {
+            return Err(anyhow!(format!("config tables string pool {}+{} out of range {}", index.strings, index.string_len, data_len)));
+        }
+        let mut tables = HashMap::with_capacity(index.tables.len());
+        let mut columns = Vec::with_capacity(index.tables.len());
+        for (i, table) in index.tables.iter().enumerate() {
+            let column_count = table.cell_name.len() as u64;
+            let cell_count = table.rows.checked_mul(column_count);
+            let cells_end = cell_count.and_then(|count| { count.checked_mul(CELL_LEN as u64) }).and_then(|len| { table.cells.checked_add(len) });
+            let nulls_end = cell_count.and_then(|count| { table.nulls.checked_add(count.div_ceil(8)) });
+            let keys_end = table.rows.checked_mul(KEY_LEN as u64).and_then(|len| { table.keys.checked_add(len) });
+            if cells_end.map(|end| { end > table.nulls }).unwrap_or(true) || nulls_end.map(|end| { end > table.keys }).unwrap_or(true)
+                || keys_end.map(|end| { end > index.strings }).unwrap_or(true) || table.cell_type.len() as u64 != column_count {
+                return Err(anyhow!(format!("config tables table {} out of range", table.name)));
+            }
+            if let Some(key_index) = table.key_indexes.iter().find(|j| { **j as u64 >= column_count }) {
+                return Err(anyhow!(format!("config tables table {} key column {} out of range {}", table.name, key_index, column_count)));
+            }
+            if tables.insert(table.name.clone(), i).is_some() {
+                return Err(anyhow!(format!("duplicate config name: {}", table.name)));
+            }
+            columns.push(table.cell_name.iter().enumerate().map(|(j, name)| { (name.clone(), j) }).collect());
+        }
+        Ok(MappedTables { map, data, index, tables, columns })
+    }
+
+    /// crc of the whole file, reads every page so it is done once after export instead of on every open
+    pub fn verify(&self) -> anyhow::Result<()> {
+        let expect_crc = u32::from_le_bytes(self.map[14..18].try_into()?);
+        let crc = crc32fast::hash(&self.map[TABLES_HEADER_LEN..]);
+        if crc != expect_crc {
+            return Err(anyhow!(format!("config tables crc mismatch, expect: {:08x}, got: {:08x}, the file is corrupted or truncated", expect_crc, crc)));
+        }
+        Ok(())
+    }
+
+    pub fn commit_id(&self) -> &str {
+        &self.index.commit_id
+    }
+
+    pub fn digest(&self) -> &str {
+        &self.index.digest
+    }
+
+    pub fn create_mills(&self) -> u128 {
+        self.index.create_mills
+    }
+
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.index.tables.iter().map(|table| { table.name.as_str() })
+    }
+
+    pub fn get(&self, name: &str) -> Option<MappedTable<'_>> {
+        let i = *self.tables.get(name)?;
+        Some(MappedTable { tables: self, index: &self.index.tables[i], columns: &self.columns[i] })
+    }
+
+    pub fn table(&self, name: &str) -> anyhow::Result<MappedTable<'_>> {
+        self.get(name).ok_or(anyhow!(format!("config {} not found", name)))
+    }
+
+    fn bytes(&self, offset: u64, len: usize) -> &[u8] {
+        let start = self.data + offset as usize;
+        &self.map[start..start + len]
+    }
+
+    fn text(&self, slot: [u8; 8]) -> anyhow::Result<&str> {
+        let offset = u32::from_le_bytes(slot[..4].try_into()?) as u64;
+        let len = u32::from_le_bytes(slot[4..].try_into()?) as u64;
+        if offset + len > self.index.string_len {
+            return Err(anyhow!(format!("text {}+{} out of the string pool", offset, len)));
+        }
+        Ok(std::str::from_utf8(self.bytes(self.index.strings + offset, len as usize))?)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MappedTable<'a> {
+    tables: &'a MappedTables,
+    index: &'a TableIndex,
+    columns: &'a HashMap<String, usize>,
+}
+
+impl<'a> MappedTable<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.index.name
+    }
+
+    pub fn key_names(&self) -> Vec<&'a str> {
+        self.index.key_indexes.iter().map(|i| { self.index.cell_name[*i].as_str() }).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.rows as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.rows == 0
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.get(name).copied()
+    }
+
+    pub fn cell_type(&self, column: usize) -> &'a CellType {
+        &self.index.cell_type[column]
+    }
+
+    /// binary search of the sorted keys, composite keys are joined by `COMPOSITE_KEY_SEPARATOR`
+    pub fn row(&self, key: impl ToString) -> Option<MappedRow<'a>> {
+        let key = key.to_string();
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            let entry = self.tables.bytes(self.index.keys + (middle * KEY_LEN) as u64, KEY_LEN);
+            let text = self.tables.text(entry[..8].try_into().ok()?).ok()?;
+            match text.cmp(key.as_str()) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => {
+                    let index = u32::from_le_bytes(entry[8..].try_into().ok()?) as usize;
+                    return (index < self.len()).then_some(MappedRow { table: *self, index });
+                }
+            }
+        }
+        None
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item=MappedRow<'a>> {
+        let table = *self;
+        (0..self.len()).map(move |index| { MappedRow { table, index } })
+    }
+
+    pub fn get<T>(&self, key: impl ToString, column: &str) -> anyhow::Result<T> where T: MappedValue<'a> {
+        let key = key.to_string();
+        let row = self.row(&key).ok_or(anyhow!(format!("{} key {} not found", self.name(), key)))?;
+        row.get(column)
+    }
+}
+
+/// one cell of a mapped table, a number or the text it refers to
+#[derive(Debug, Clone, Copy)]
+pub struct MappedCell<'a> {
+    tables: &'a MappedTables,
+    slot: [u8; 8],
+}
+
+impl<'a> MappedCell<'a> {
+    pub fn bits(&self) -> u64 {
+        u64::from_le_bytes(self.slot)
+    }
+
+    pub fn text(&self) -> anyhow::Result<&'a str> {
+        self.tables.text(self.slot)
+    }
+}
+
+/// rust types that a mapped cell can be read as, the owned ones accept the `CellType`s of their `CellValue`
+pub trait MappedValue<'a>: Sized {
+    fn accept(ty: &CellType) -> bool;
+
+    fn read(cell: MappedCell<'a>) -> anyhow::Result<Self>;
+}
+
+macro_rules! mapped_fixed {
+    ($ty:ty, $bits:ident, $read:expr) => {
+        impl<'a> MappedValue<'a> for $ty {
+            fn accept(ty: &CellType) -> bool {
+                <$ty as CellValue>::accept(ty)
+            }
+
+            fn read(cell: MappedCell<'a>) -> anyhow::Result<Self> {
+                let $bits = cell.bits();
+                Ok($read)
+            }
+        }
+    };
+}
+
+mapped_fixed!(u32, bits, bits as u32);
+mapped_fixed!(i32, bits, bits as i32);
+mapped_fixed!(i64, bits, bits as i64);
+mapped_fixed!(bool, bits, bits != 0);
+mapped_fixed!(f32, bits, f32::from_bits(bits as u32));
+mapped_fixed!(f64, bits, f64::from_bits(bits));
+mapped_fixed!(Duration, bits, Duration::from_secs(bits));
+
+impl<'a> MappedValue<'a> for DateTime {
+    fn accept(ty: &CellType) -> bool {
+        <DateTime as CellValue>::accept(ty)
+    }
+
+    fn read(cell: MappedCell<'a>) -> anyhow::Result<Self> {
+        let seconds = cell.bits() as i64;
+        chrono::Utc.timestamp_opt(seconds, 0).single().ok_or_else(|| { anyhow!(format!("datetime {} out of range", seconds)) })
+    }
+}
+
+/// texts of string and lang cells borrowed from the map
+impl<'a> MappedValue<'a> for &'a str {
+    fn accept(ty: &CellType) -> bool {
+        <String as CellValue>::accept(ty)
+    }
+
+    fn read(cell: MappedCell<'a>) -> anyhow::Result<Self> {
+        cell.text()
+    }
+}
+
+macro_rules! mapped_text {
+    ($ty:ty) => {
+        impl<'a> MappedValue<'a> for $ty {
+            fn accept(ty: &CellType) -> bool {
+                <$ty as CellValue>::accept(ty)
+            }
+
+            fn read(cell: MappedCell<'a>) -> anyhow::Result<Self> {
+                Parse::<$ty>::parse(&cell.text()?.to_string())
+            }
+        }
+    };
+}
+
+mapped_text!(String);
+mapped_text!(CronSchedule);
+mapped_text!(Vector3ArrayInt);
+mapped_text!(Vector3Int);
+mapped_text!(Vector2Int);
+mapped_text!(Vector3UInt);
+mapped_text!(Vector2UInt);
+mapped_text!(Vector2ArrayInt);
+mapped_text!(ArrayInt);
+mapped_text!(ArrayUInt);
+mapped_text!(DictionaryStringFloat);
+mapped_text!(DictionaryStringInt);
+mapped_text!(Vector2Float);
+mapped_text!(Vector3Float);
+mapped_text!(Vector2String);
+
+#[derive(Debug, Clone, Copy)]
+pub struct MappedRow<'a> {
+    table: MappedTable<'a>,
+    index: usize,
+}
+
+impl<'a> MappedRow<'a> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    fn column_index(&self, column: &str) -> anyhow::Result<usize> {
+        self.table.column(column).ok_or(anyhow!(format!("{} column {} not found", self.table.name(), column)))
+    }
+
+    fn cell(&self, column: usize) -> MappedCell<'a> {
+        let index = self.table.index;
+        let offset = index.cells + ((self.index * index.cell_name.len() + column) * CELL_LEN) as u64;
+        let slot = self.table.tables.bytes(offset, CELL_LEN).try_into().expect("cell is 8 bytes");
+        MappedCell { tables: self.table.tables, slot }
+    }
+
+    pub fn is_null(&self, column: usize) -> bool {
+        let index = self.table.index;
+        let bit = self.index * index.cell_name.len() + column;
+        self.table.tables.bytes(index.nulls + (bit / 8) as u64, 1)[0] & (1 << (bit % 8)) != 0
+    }
+
+    pub fn get<T>(&self, column: &str) -> anyhow::Result<T> where T: MappedValue<'a> {
+        let i = self.column_index(column)?;
+        let ty = self.table.cell_type(i);
+        if !T::accept(ty) {
+            return Err(anyhow!(format!("{} column {} is {}, cannot read as {}", self.table.name(), column, ty, std::any::type_name::<T>())));
+        }
+        T::read(self.cell(i)).context(format!("{} row {} column {} incorrect cell data", self.table.name(), self.index + 1, column))
+    }
+
+    /// `None` for the empty cells of a nullable column
+    pub fn get_option<T>(&self, column: &str) -> anyhow::Result<Option<T>> where T: MappedValue<'a> {
+        if self.is_null(self.column_index(column)?) {
+            return Ok(None);
+        }
+        self.get(column).map(Some)
+    }
+
+    /// an array or struct cell deserialized by its field names
+    pub fn get_composite<T: serde::de::DeserializeOwned>(&self, column: &str) -> anyhow::Result<T> {
+        let i = self.column_index(column)?;
+        let ty = self.table.cell_type(i);
+        if !ty.is_composite() {
+            return Err(anyhow!(format!("{} column {} is {}, cannot read as {}", self.table.name(), column, ty, std::any::type_name::<T>())));
+        }
+        let data = self.cell(i).text()?;
+        parse_composite(ty, data).context(format!("{} row {} column {} incorrect cell data: {}", self.table.name(), self.index + 1, column, data))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use crate::excel::convert::Vector2Int;
+    use crate::excel::excel_define::{CellRule, CellType, GameConfig, GameConfigs, KeyType};
+    use crate::excel::mapped_table::{encode_tables, MappedTables, TABLES_HEADER_LEN, TablesIndex, write_tables};
+
+    #[test]
+    fn test_mapped_tables() -> anyhow::Result<()> {
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "name".to_string(), "pos".to_string(), "price".to_string(), "weight".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::Server, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::String, CellType::Vector2Int, CellType::Long, CellType::Float])
+            .cell_rule(vec![vec![], vec![], vec![], vec![CellRule::Nullable], vec![]])
+            .data(vec![
+                vec!["1002".to_string(), "shield".to_string(), "3,4".to_string(), "".to_string(), "2.5".to_string()],
+                vec!["1001".to_string(), "sword".to_string(), "1,2".to_string(), "-10".to_string(), "1.5".to_string()],
+                vec!["999".to_string(), "sword".to_string(), "".to_string(), "5".to_string(), "".to_string()],
+            ])
+            .build();
+        let game_configs = GameConfigs::builder().commit_id("abc".to_string()).create_mills(1).data(vec![config]).build();
+        assert_eq!(encode_tables(&game_configs)?, encode_tables(&game_configs)?);
+        let path = env::temp_dir().join("test_mapped_tables.tables");
+        write_tables(&game_configs, &path)?;
+
+        let tables = MappedTables::open(&path)?;
+        tables.verify()?;
+        assert_eq!(tables.commit_id(), "abc");
+        let item = tables.table("item")?;
+        assert_eq!(item.key_names(), vec!["id"]);
+        assert_eq!(item.len(), 3);
+        assert_eq!(item.get::<&str>(1001, "name")?, "sword");
+        let row = item.row(1002).unwrap();
+        assert_eq!(row.get::<u32>("id")?, 1002);
+        assert_eq!(row.get::<Vector2Int>("pos")?, (3, 4));
+        assert_eq!(row.get::<f32>("weight")?, 2.5);
+        assert_eq!(row.get_option::<i64>("price")?, None);
+        assert_eq!(item.row(1001).unwrap().get_option::<i64>("price")?, Some(-10));
+        assert_eq!(item.row(999).unwrap().get::<Vector2Int>("pos")?, (0, 0));
+        assert!(row.get::<i32>("id").is_err());
+        assert!(item.row(1003).is_none());
+        assert_eq!(item.rows().map(|row| { row.get::<u32>("id") }).collect::<anyhow::Result<Vec<_>>>()?, vec![1002, 1001, 999]);
+        drop(tables);
+
+        let mut bytes = std::fs::read(&path)?;
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, &bytes)?;
+        let tables = MappedTables::open(&path)?;
+        assert!(tables.verify().unwrap_err().to_string().contains("crc mismatch"));
+        drop(tables);
+
+        // a corrupted index is an error when opened instead of a panic when read
+        let index_len = u64::from_le_bytes(bytes[18..26].try_into()?) as usize;
+        let corrupt = |update: &dyn Fn(&mut TablesIndex)| -> anyhow::Result<String> {
+            let mut index: TablesIndex = bincode::deserialize(&bytes[TABLES_HEADER_LEN..TABLES_HEADER_LEN + index_len])?;
+            update(&mut index);
+            let mut corrupted = bytes.clone();
+            corrupted.splice(TABLES_HEADER_LEN..TABLES_HEADER_LEN + index_len, bincode::serialize(&index)?);
+            std::fs::write(&path, &corrupted)?;
+            Ok(format!("{:#}", MappedTables::open(&path).err().unwrap()))
+        };
+        assert!(corrupt(&|index| { index.tables[0].nulls = index.tables[0].keys })?.contains("out of range"));
+        assert!(corrupt(&|index| { index.tables[0].key_indexes = vec![5] })?.contains("key column 5 out of range"));
+        Ok(())
+    }
+}
@@ -1,4 +1,21 @@
 pub mod excel_define;
 pub mod checker;
 pub mod convert;
-pub mod config_loader;
\ No newline at end of file
+pub mod config_loader;
+pub mod rust_writer;
+pub mod config_reload;
+pub mod report;
+pub mod manifest;
+pub mod revision;
+pub mod proto_writer;
+pub mod enum_define;
+pub mod type_parser;
+pub mod composite;
+pub mod lang_define;
+pub mod time_define;
+pub mod lua_emitter;
+pub mod workbook;
+pub mod diff;
+pub mod bundle;
+pub mod mapped_table;
+pub mod export;
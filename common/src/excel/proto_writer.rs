@@ -0,0 +1,533 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use convert_case::{Case, Casing};
+
+use crate::excel::composite::CompositeValue;
+use crate::excel::convert::{ArrayInt, ArrayUInt, CronSchedule, DateTime, DictionaryStringFloat, Duration, DictionaryStringInt, Parse, Vector2ArrayInt, Vector2Float, Vector2Int, Vector2String, Vector2UInt, Vector3ArrayInt, Vector3Float, Vector3Int, Vector3UInt};
+use crate::excel::excel_define::{CellType, GameConfig};
+use crate::excel::report::ExcelError;
+
+/// same package style as the protocol protos, so the files can be compiled by the `proto` crate
+pub const PROTO_PACKAGE: &str = "com.youzu.got.config";
+pub const PROTO_TYPES_FILE: &str = "excel_types.proto";
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+fn write_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_tag(field: u32, wire_type: u64, buf: &mut Vec<u8>) {
+    write_varint(((field as u64) << 3) | wire_type, buf);
+}
+
+fn write_len(field: u32, bytes: &[u8], buf: &mut Vec<u8>) {
+    write_tag(field, WIRE_LEN, buf);
+    write_varint(bytes.len() as u64, buf);
+    buf.extend_from_slice(bytes);
+}
+
+/// protobuf wire encoding of a parsed cell value, scalar default values are skipped like proto3 does
+pub trait ToProto {
+    fn encode(&self, field: u32, buf: &mut Vec<u8>);
+}
+
+macro_rules! varint_to_proto {
+    ($ty:ty) => {
+        impl ToProto for $ty {
+            fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+                if *self != <$ty>::default() {
+                    write_tag(field, WIRE_VARINT, buf);
+                    // negative int32 is sign extended to ten bytes as the protobuf spec requires
+                    write_varint(*self as u64, buf);
+                }
+            }
+        }
+    };
+}
+
+varint_to_proto!(u32);
+varint_to_proto!(i32);
+varint_to_proto!(i64);
+varint_to_proto!(u64);
+varint_to_proto!(bool);
+
+macro_rules! fixed_to_proto {
+    ($ty:ty,$wire_type:expr) => {
+        impl ToProto for $ty {
+            fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+                if *self != 0.0 {
+                    write_tag(field, $wire_type, buf);
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+        }
+    };
+}
+
+fixed_to_proto!(f32,WIRE_FIXED32);
+fixed_to_proto!(f64,WIRE_FIXED64);
+
+impl ToProto for String {
+    fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+        if !self.is_empty() {
+            write_len(field, self.as_bytes(), buf);
+        }
+    }
+}
+
+macro_rules! vector2_to_proto {
+    ($name:ident) => {
+        impl ToProto for $name {
+            fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+                let mut message = vec![];
+                self.0.encode(1, &mut message);
+                self.1.encode(2, &mut message);
+                write_len(field, &message, buf);
+            }
+        }
+    };
+}
+
+vector2_to_proto!(Vector2Int);
+vector2_to_proto!(Vector2UInt);
+vector2_to_proto!(Vector2Float);
+vector2_to_proto!(Vector2String);
+
+macro_rules! vector3_to_proto {
+    ($name:ident) => {
+        impl ToProto for $name {
+            fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+                let mut message = vec![];
+                self.0.encode(1, &mut message);
+                self.1.encode(2, &mut message);
+                self.2.encode(3, &mut message);
+                write_len(field, &message, buf);
+            }
+        }
+    };
+}
+
+vector3_to_proto!(Vector3Int);
+vector3_to_proto!(Vector3UInt);
+vector3_to_proto!(Vector3Float);
+
+/// repeated scalars are packed in proto3
+macro_rules! array_to_proto {
+    ($name:ident) => {
+        impl ToProto for $name {
+            fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+                if !self.is_empty() {
+                    let mut packed = vec![];
+                    for value in self {
+                        write_varint(*value as u64, &mut packed);
+                    }
+                    write_len(field, &packed, buf);
+                }
+            }
+        }
+    };
+}
+
+array_to_proto!(ArrayInt);
+array_to_proto!(ArrayUInt);
+
+macro_rules! vector_array_to_proto {
+    ($name:ident) => {
+        impl ToProto for $name {
+            fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+                for vector in self {
+                    vector.encode(field, buf);
+                }
+            }
+        }
+    };
+}
+
+vector_array_to_proto!(Vector2ArrayInt);
+vector_array_to_proto!(Vector3ArrayInt);
+
+/// map entries are written in key order so the output is stable
+macro_rules! map_to_proto {
+    ($name:ident) => {
+        impl ToProto for $name {
+            fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+                let mut keys = self.keys().collect::<Vec<_>>();
+                keys.sort();
+                for k in keys {
+                    let mut entry = vec![];
+                    k.encode(1, &mut entry);
+                    self[k].encode(2, &mut entry);
+                    write_len(field, &entry, buf);
+                }
+            }
+        }
+    };
+}
+
+map_to_proto!(DictionaryStringFloat);
+map_to_proto!(DictionaryStringInt);
+
+/// arrays of numbers are packed, other arrays write every element, an array nested in an array is wrapped in a
+/// message with the elements as field 1, struct fields are numbered in order
+impl ToProto for CompositeValue {
+    fn encode(&self, field: u32, buf: &mut Vec<u8>) {
+        match self {
+            CompositeValue::UInt(value) => value.encode(field, buf),
+            CompositeValue::Int(value) => value.encode(field, buf),
+            CompositeValue::Long(value) => value.encode(field, buf),
+            CompositeValue::Float(value) => value.encode(field, buf),
+            CompositeValue::Double(value) => value.encode(field, buf),
+            CompositeValue::Bool(value) => value.encode(field, buf),
+            CompositeValue::String(value) => value.encode(field, buf),
+            CompositeValue::Array(values) => {
+                let mut packed = vec![];
+                for value in values {
+                    match value {
+                        CompositeValue::UInt(v) => write_varint(*v as u64, &mut packed),
+                        CompositeValue::Int(v) => write_varint(*v as u64, &mut packed),
+                        CompositeValue::Long(v) => write_varint(*v as u64, &mut packed),
+                        CompositeValue::Bool(v) => write_varint(*v as u64, &mut packed),
+                        CompositeValue::Float(v) => packed.extend_from_slice(&v.to_le_bytes()),
+                        CompositeValue::Double(v) => packed.extend_from_slice(&v.to_le_bytes()),
+                        CompositeValue::String(v) => write_len(field, v.as_bytes(), buf),
+                        CompositeValue::Array(_) => {
+                            let mut message = vec![];
+                            value.encode(1, &mut message);
+                            write_len(field, &message, buf);
+                        }
+                        CompositeValue::Struct(_) => value.encode(field, buf),
+                    }
+                }
+                if !packed.is_empty() {
+                    write_len(field, &packed, buf);
+                }
+            }
+            CompositeValue::Struct(fields) => {
+                let mut message = vec![];
+                for (i, (_, value)) in fields.iter().enumerate() {
+                    value.encode(i as u32 + 1, &mut message);
+                }
+                write_len(field, &message, buf);
+            }
+        }
+    }
+}
+
+/// writes a `.proto` schema and the protobuf encoded rows of a sheet
+///
+/// every sheet becomes a `{Name}Config` row message and a `{Name}Table` holding all rows,
+/// field numbers follow the column order so reordering columns changes the schema
+pub struct ProtoWriter;
+
+impl ProtoWriter {
+    /// vector messages shared by all sheets
+    pub fn write_types() -> String {
+        let mut proto = Self::header();
+        let vectors = [
+            ("Vector2Int", "int32", 2),
+            ("Vector3Int", "int32", 3),
+            ("Vector2UInt", "uint32", 2),
+            ("Vector3UInt", "uint32", 3),
+            ("Vector2Float", "float", 2),
+            ("Vector3Float", "float", 3),
+            ("Vector2String", "string", 2),
+        ];
+        for (name, ty, len) in vectors {
+            proto.push_str(&format!("\nmessage {} {{\n", name));
+            for (i, field) in ["x", "y", "z"].iter().take(len).enumerate() {
+                proto.push_str(&format!("  {} {} = {};\n", ty, field, i + 1));
+            }
+            proto.push_str("}\n");
+        }
+        proto
+    }
+
+    pub fn write_schema(config: &GameConfig) -> anyhow::Result<String> {
+        let message = proto_ident(&config.name, Case::Pascal)?;
+        let mut proto = Self::header();
+        if config.cell_type.iter().any(|ty| { ty.proto_type().map(|proto_type| { proto_type.contains("Vector") }).unwrap_or(false) }) {
+            proto.push_str(&format!("\nimport \"{}\";\n", PROTO_TYPES_FILE));
+        }
+        let mut messages = vec![];
+        // nested messages must not clash with each other or with the row and table messages
+        let mut message_names = HashSet::from([format!("{}Config", message), format!("{}Table", message)]);
+        let mut fields = vec![];
+        let mut field_names = HashMap::new();
+        for (j, (name, ty)) in config.cell_name.iter().zip(&config.cell_type).enumerate() {
+            let field = proto_ident(name, Case::Snake).map_err(|error| { config.column_error(j, error) })?;
+            if let Some(other) = field_names.insert(field.clone(), name.clone()) {
+                return Err(config.column_error(j, format!("{} and {} generate the same proto field: {}", other, name, field)));
+            }
+            let nested = format!("{}{}", message, proto_ident(name, Case::Pascal).map_err(|error| { config.column_error(j, error) })?);
+            let mut field_type = field_type(ty, &nested, &mut messages, &mut message_names).map_err(|error| { config.column_error(j, error) })?;
+            // messages already have presence, repeated and map fields cannot be optional
+            if config.nullable(j) && is_scalar(&field_type) {
+                field_type = format!("optional {}", field_type);
+            }
+            fields.push(format!("  {} {} = {};\n", field_type, field, j + 1));
+        }
+        for nested in messages {
+            proto.push_str(&format!("\n{}", nested));
+        }
+        proto.push_str(&format!("\nmessage {}Config {{\n", message));
+        proto.push_str(&fields.concat());
+        proto.push_str("}\n");
+        proto.push_str(&format!("\nmessage {}Table {{\n", message));
+        proto.push_str(&format!("  repeated {}Config rows = 1;\n", message));
+        proto.push_str("  string digest = 2;\n");
+        proto.push_str("}\n");
+        Ok(proto)
+    }
+
+    /// an encoded `{Name}Table` message
+    pub fn write_data(config: &GameConfig) -> anyhow::Result<Vec<u8>> {
+        let mut table = vec![];
+        for (i, row_data) in config.data.iter().enumerate() {
+            let mut row = vec![];
+            for (j, (cell_data, ty)) in row_data.iter().zip(&config.cell_type).enumerate() {
+                if config.is_null(i, j) {
+                    continue;
+                }
+                let len = row.len();
+                Self::write(ty, cell_data, j as u32 + 1, &mut row).map_err(|error| { anyhow::Error::from(ExcelError::at(&config.cell_location(i, j), cell_data, error)) })?;
+                if config.nullable(j) && row.len() == len {
+                    Self::write_present(ty, j as u32 + 1, &mut row);
+                }
+            }
+            write_len(1, &row, &mut table);
+        }
+        config.digest()?.encode(2, &mut table);
+        Ok(table)
+    }
+
+    pub fn write(ty: &CellType, data: &String, field: u32, buf: &mut Vec<u8>) -> anyhow::Result<()> {
+        match ty {
+            CellType::UInt => {
+                crate::parse!(data,u32).encode(field, buf);
+            }
+            CellType::Int => {
+                crate::parse!(data,i32).encode(field, buf);
+            }
+            CellType::Long => {
+                crate::parse!(data,i64).encode(field, buf);
+            }
+            CellType::String => {
+                crate::parse!(data,String).encode(field, buf);
+            }
+            CellType::Bool => {
+                crate::parse!(data,bool).encode(field, buf);
+            }
+            CellType::Vector3ArrayInt => {
+                crate::parse!(data,Vector3ArrayInt).encode(field, buf);
+            }
+            CellType::Vector3Int => {
+                crate::parse!(data,Vector3Int).encode(field, buf);
+            }
+            CellType::Vector2Int => {
+                crate::parse!(data,Vector2Int).encode(field, buf);
+            }
+            CellType::Vector3UInt => {
+                crate::parse!(data,Vector3UInt).encode(field, buf);
+            }
+            CellType::Vector2UInt => {
+                crate::parse!(data,Vector2UInt).encode(field, buf);
+            }
+            CellType::Vector2ArrayInt => {
+                crate::parse!(data,Vector2ArrayInt).encode(field, buf);
+            }
+            CellType::ArrayInt => {
+                crate::parse!(data,ArrayInt).encode(field, buf);
+            }
+            CellType::ArrayUInt => {
+                crate::parse!(data,ArrayUInt).encode(field, buf);
+            }
+            CellType::DictionaryStringFloat => {
+                crate::parse!(data,DictionaryStringFloat).encode(field, buf);
+            }
+            CellType::DictionaryStringInt => {
+                crate::parse!(data,DictionaryStringInt).encode(field, buf);
+            }
+            CellType::Lang => {
+                crate::parse!(data,String).encode(field, buf);
+            }
+            CellType::Float => {
+                crate::parse!(data,f32).encode(field, buf);
+            }
+            CellType::Double => {
+                crate::parse!(data,f64).encode(field, buf);
+            }
+            CellType::Vector2Float => {
+                crate::parse!(data,Vector2Float).encode(field, buf);
+            }
+            CellType::Vector3Float => {
+                crate::parse!(data,Vector3Float).encode(field, buf);
+            }
+            CellType::Vector2String => {
+                crate::parse!(data,Vector2String).encode(field, buf);
+            }
+            CellType::Enum(_) | CellType::Flags(_) => {
+                crate::parse!(data,i32).encode(field, buf);
+            }
+            CellType::Array(_) | CellType::Struct(_) => {
+                CompositeValue::parse(ty, data)?.encode(field, buf);
+            }
+            CellType::DateTime => {
+                crate::parse!(data,DateTime).timestamp().encode(field, buf);
+            }
+            CellType::Duration => {
+                crate::parse!(data,Duration).as_secs().encode(field, buf);
+            }
+            CellType::Cron => {
+                crate::parse!(data,CronSchedule).expression().to_string().encode(field, buf);
+            }
+        }
+        Ok(())
+    }
+
+    /// the default value of an optional scalar, which proto3 only skips for fields without presence
+    fn write_present(ty: &CellType, field: u32, buf: &mut Vec<u8>) {
+        match ty.proto_type() {
+            Some("float") => {
+                write_tag(field, WIRE_FIXED32, buf);
+                buf.extend_from_slice(&0f32.to_le_bytes());
+            }
+            Some("double") => {
+                write_tag(field, WIRE_FIXED64, buf);
+                buf.extend_from_slice(&0f64.to_le_bytes());
+            }
+            Some("string") => write_len(field, &[], buf),
+            Some(proto_type) if is_scalar(proto_type) => {
+                write_tag(field, WIRE_VARINT, buf);
+                write_varint(0, buf);
+            }
+            _ => {}
+        }
+    }
+
+    fn header() -> String {
+        format!("// generated by excel_tool, do not edit\nsyntax = \"proto3\";\n\npackage {};\n", PROTO_PACKAGE)
+    }
+}
+
+/// proto type of a column or struct field, a message named `name` is added to `messages` for every struct and every
+/// array nested in an array, `names` holds the message names already taken
+fn field_type(ty: &CellType, name: &str, messages: &mut Vec<String>, names: &mut HashSet<String>) -> anyhow::Result<String> {
+    match ty {
+        CellType::Array(element) => {
+            if let CellType::Array(_) = element.as_ref() {
+                let values = field_type(element, &format!("{}Values", name), messages, names)?;
+                take_message_name(name, names)?;
+                messages.push(format!("message {} {{\n  {} values = 1;\n}}\n", name, values));
+                Ok(format!("repeated {}", name))
+            } else {
+                Ok(format!("repeated {}", field_type(element, name, messages, names)?))
+            }
+        }
+        CellType::Struct(fields) => {
+            let mut formatted_fields = vec![];
+            for (i, field) in fields.iter().enumerate() {
+                let field_type = field_type(&field.ty, &format!("{}{}", name, proto_ident(&field.name, Case::Pascal)?), messages, names)?;
+                formatted_fields.push(format!("  {} {} = {};\n", field_type, proto_ident(&field.name, Case::Snake)?, i + 1));
+            }
+            take_message_name(name, names)?;
+            messages.push(format!("message {} {{\n{}}}\n", name, formatted_fields.concat()));
+            Ok(name.to_string())
+        }
+        other => other.proto_type().map(ToString::to_string).ok_or_else(|| { anyhow!(format!("{} has no proto type", other)) }),
+    }
+}
+
+fn take_message_name(name: &str, names: &mut HashSet<String>) -> anyhow::Result<()> {
+    if !names.insert(name.to_string()) {
+        return Err(anyhow!(format!("proto message {} is generated twice, rename the column or the struct field", name)));
+    }
+    Ok(())
+}
+
+/// scalar types are lowercase, messages are pascal case
+fn is_scalar(field_type: &str) -> bool {
+    !field_type.starts_with("repeated") && !field_type.starts_with("map<") && field_type.starts_with(|c: char| { c.is_ascii_lowercase() })
+}
+
+/// protobuf identifier of a sheet or column name
+pub fn proto_ident(name: &str, case: Case) -> anyhow::Result<String> {
+    let ident = name.to_case(case);
+    let valid = ident.chars().next().map(|c| { c.is_ascii_alphabetic() || c == '_' }).unwrap_or(false) && ident.chars().all(|c| { c.is_ascii_alphanumeric() || c == '_' });
+    if !valid {
+        return Err(anyhow!(format!("{} is not a valid protobuf identifier", name)));
+    }
+    Ok(ident)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::excel::excel_define::{CellType, GameConfig, KeyType};
+    use crate::excel::proto_writer::ProtoWriter;
+
+    #[test]
+    fn test_write_proto() -> anyhow::Result<()> {
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "pos".to_string(), "tags".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::Vector2Int, CellType::ArrayUInt])
+            .data(vec![vec!["1".to_string(), "1,-1".to_string(), "".to_string()]])
+            .build();
+        let schema = ProtoWriter::write_schema(&config)?;
+        assert!(schema.contains("import \"excel_types.proto\";"));
+        assert!(schema.contains("message ItemConfig {\n  uint32 id = 1;\n  Vector2Int pos = 2;\n  repeated uint32 tags = 3;\n}"));
+        assert!(schema.contains("message ItemTable {\n  repeated ItemConfig rows = 1;\n  string digest = 2;\n}"));
+
+        let data = ProtoWriter::write_data(&config)?;
+        let row = [0x08, 0x01, 0x12, 0x0d, 0x08, 0x01, 0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(&data[..2], &[0x0a, row.len() as u8]);
+        assert_eq!(&data[2..2 + row.len()], &row);
+        // digest is the only table field left
+        assert_eq!(data[2 + row.len()], 0x12);
+
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["rewards".to_string()])
+            .key_type(vec![KeyType::All])
+            .cell_type(vec![CellType::parse_type("array<struct{id:uint,grid:array<array<int>>}>")?])
+            .data(vec![vec!["{1,[[2]]}".to_string()]])
+            .build();
+        let schema = ProtoWriter::write_schema(&config)?;
+        assert!(schema.contains("message ItemRewardsGrid {\n  repeated int32 values = 1;\n}"));
+        assert!(schema.contains("message ItemRewards {\n  uint32 id = 1;\n  repeated ItemRewardsGrid grid = 2;\n}"));
+        assert!(schema.contains("message ItemConfig {\n  repeated ItemRewards rewards = 1;\n}"));
+        let data = ProtoWriter::write_data(&config)?;
+        assert_eq!(&data[..11], &[0x0a, 0x09, 0x0a, 0x07, 0x08, 0x01, 0x12, 0x03, 0x0a, 0x01, 0x02]);
+
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["itemId".to_string(), "item_id".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::UInt])
+            .data(vec![])
+            .build();
+        assert!(ProtoWriter::write_schema(&config).unwrap_err().to_string().contains("itemId and item_id generate the same proto field: item_id"));
+
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["table".to_string(), "reward_item".to_string(), "reward".to_string()])
+            .key_type(vec![KeyType::All, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::parse_type("array<int>")?, CellType::parse_type("struct{id:uint}")?, CellType::parse_type("struct{item:struct{id:uint}}")?])
+            .data(vec![])
+            .build();
+        assert!(ProtoWriter::write_schema(&config).unwrap_err().to_string().contains("proto message ItemRewardItem is generated twice"));
+        let mut config = config;
+        config.cell_type[0] = CellType::parse_type("struct{id:uint}")?;
+        assert!(ProtoWriter::write_schema(&config).unwrap_err().to_string().contains("proto message ItemTable is generated twice"));
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use anyhow::Context;
+use tracing::error;
+
+/// where a cell lives in the source workbook
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct CellLocation {
+    pub file: String,
+    pub sheet: String,
+    /// excel row number, 1 based
+    pub row: usize,
+    /// excel column letters, e.g. `B`
+    pub column: String,
+    pub column_name: String,
+    pub cell_type: String,
+}
+
+impl Display for CellLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}] {}{} {}({})", self.file, self.sheet, self.column, self.row, self.column_name, self.cell_type)
+    }
+}
+
+/// an excel check failure with as much location as is known
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct ExcelError {
+    pub file: String,
+    pub sheet: String,
+    pub row: Option<usize>,
+    pub column: Option<String>,
+    pub column_name: Option<String>,
+    pub cell_type: Option<String>,
+    pub data: Option<String>,
+    pub message: String,
+}
+
+impl ExcelError {
+    pub fn at(location: &CellLocation, data: &str, message: impl ToString) -> Self {
+        ExcelError {
+            file: location.file.clone(),
+            sheet: location.sheet.clone(),
+            row: Some(location.row),
+            column: Some(location.column.clone()),
+            column_name: Some(location.column_name.clone()),
+            cell_type: Some(location.cell_type.clone()),
+            data: Some(data.to_string()),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn sheet(file: &str, sheet: &str, message: impl ToString) -> Self {
+        ExcelError {
+            file: file.to_string(),
+            sheet: sheet.to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// the location part without file and sheet, used when errors are grouped by sheet
+    fn cell(&self) -> String {
+        let mut cell = String::new();
+        if let Some(column) = &self.column {
+            cell.push_str(column);
+        }
+        if let Some(row) = self.row {
+            cell.push_str(&row.to_string());
+        }
+        if let Some(column_name) = &self.column_name {
+            cell.push_str(&format!(" {}", column_name));
+        }
+        if let Some(cell_type) = &self.cell_type {
+            cell.push_str(&format!("({})", cell_type));
+        }
+        if let Some(data) = &self.data {
+            cell.push_str(&format!(" data: {:?}", data));
+        }
+        cell.trim().to_string()
+    }
+}
+
+impl Display for ExcelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cell = self.cell();
+        if cell.is_empty() {
+            write!(f, "{} [{}] {}", self.file, self.sheet, self.message)
+        } else {
+            write!(f, "{} [{}] {}: {}", self.file, self.sheet, cell, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ExcelError {}
+
+/// excel column letters of a 1 based column number, e.g. 1 -> A, 28 -> AB
+pub fn column_letter(mut column: usize) -> String {
+    let mut letters = vec![];
+    while column > 0 {
+        let rem = (column - 1) % 26;
+        letters.push((b'A' + rem as u8) as char);
+        column = (column - 1) / 26;
+    }
+    letters.iter().rev().collect()
+}
+
+/// all errors of one excel_tool run
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ErrorReport {
+    pub errors: Vec<ExcelError>,
+    /// errors already in `errors`, so the same error is kept once without scanning them
+    #[serde(skip)]
+    seen: HashSet<ExcelError>,
+}
+
+impl ErrorReport {
+    /// errors that are not an `ExcelError` are kept with an unknown location,
+    /// the same error found in both the server and the client bundle is kept once
+    pub fn push(&mut self, error: anyhow::Error) {
+        let excel_error = match error.downcast::<ExcelError>() {
+            Ok(excel_error) => excel_error,
+            Err(error) => ExcelError {
+                message: format!("{:#}", error),
+                ..Default::default()
+            },
+        };
+        if self.seen.insert(excel_error.clone()) {
+            self.errors.push(excel_error);
+        }
+    }
+
+    pub fn extend(&mut self, errors: Vec<anyhow::Error>) {
+        for error in errors {
+            self.push(error);
+        }
+    }
+
+    /// errors collected by another thread, appended in their order
+    pub fn merge(&mut self, other: ErrorReport) {
+        for error in other.errors {
+            if self.seen.insert(error.clone()) {
+                self.errors.push(error);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// errors grouped by workbook and sheet
+    pub fn group(&self) -> BTreeMap<(&str, &str), Vec<&ExcelError>> {
+        let mut group: BTreeMap<(&str, &str), Vec<&ExcelError>> = BTreeMap::new();
+        for error in &self.errors {
+            group.entry((error.file.as_str(), error.sheet.as_str())).or_default().push(error);
+        }
+        group
+    }
+
+    pub fn log(&self) {
+        for ((file, sheet), errors) in self.group() {
+            error!("{} [{}] {} errors:", file, sheet, errors.len());
+            for error in errors {
+                let cell = error.cell();
+                if cell.is_empty() {
+                    error!("    {}", error.message);
+                } else {
+                    error!("    {}: {}", cell, error.message);
+                }
+            }
+        }
+    }
+
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).context("failed to serialize error report")?;
+        std::fs::write(path, json).context(format!("failed to write error report: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::excel::report::{column_letter, ErrorReport, ExcelError};
+
+    #[test]
+    fn test_report() {
+        assert_eq!(column_letter(1), "A");
+        assert_eq!(column_letter(26), "Z");
+        assert_eq!(column_letter(28), "AB");
+        let mut report = ErrorReport::default();
+        report.push(ExcelError::sheet("item.xlsx", "item", "allkey|serverkey|clientkey not found").into());
+        report.push(anyhow::anyhow!("unknown"));
+        report.push(anyhow::anyhow!("unknown"));
+        assert_eq!(report.len(), 2);
+        assert_eq!(report.errors[0].to_string(), "item.xlsx [item] allkey|serverkey|clientkey not found");
+        assert_eq!(report.group().len(), 2);
+        let mut other = ErrorReport::default();
+        other.push(anyhow::anyhow!("unknown"));
+        other.push(ExcelError::sheet("shop.xlsx", "shop", "duplicate key").into());
+        report.merge(other);
+        assert_eq!(report.len(), 3);
+        assert_eq!(report.errors[2].sheet, "shop");
+    }
+}
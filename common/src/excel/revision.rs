@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use sha2::{Digest, Sha256};
+
+/// sha256 in hex
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| { format!("{:02x}", b) }).collect()
+}
+
+/// commit id of HEAD of the git repository containing `path`, read from `.git` directly without running git
+pub fn git_commit_id<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
+    let path = path.as_ref();
+    let path = path.canonicalize().context(format!("failed to canonicalize: {}", path.display()))?;
+    let git_dir = find_git_dir(&path)?;
+    // linked worktrees keep their own HEAD but share refs with the main repository
+    let common_dir = match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir.clone(),
+    };
+    let head = read_trimmed(&git_dir.join("HEAD"))?;
+    let commit_id = match head.strip_prefix("ref:") {
+        None => head,
+        Some(reference) => resolve_ref(&git_dir, &common_dir, reference.trim())?,
+    };
+    if !is_commit_id(&commit_id) {
+        return Err(anyhow!(format!("invalid commit id: {} in {}", commit_id, git_dir.display())));
+    }
+    Ok(commit_id)
+}
+
+fn find_git_dir(path: &Path) -> anyhow::Result<PathBuf> {
+    for dir in path.ancestors() {
+        let git = dir.join(".git");
+        if git.is_dir() {
+            return Ok(git);
+        }
+        if git.is_file() {
+            let content = read_trimmed(&git)?;
+            let git_dir = content.strip_prefix("gitdir:").ok_or_else(|| { anyhow!(format!("invalid git file: {}", git.display())) })?;
+            return Ok(dir.join(git_dir.trim()));
+        }
+    }
+    Err(anyhow!(format!("no git repository found for: {}", path.display())))
+}
+
+fn resolve_ref(git_dir: &Path, common_dir: &Path, reference: &str) -> anyhow::Result<String> {
+    for dir in [git_dir, common_dir] {
+        let file = dir.join(reference);
+        if file.is_file() {
+            return read_trimmed(&file);
+        }
+    }
+    let packed_refs = common_dir.join("packed-refs");
+    if packed_refs.is_file() {
+        let content = std::fs::read_to_string(&packed_refs).context(format!("failed to read: {}", packed_refs.display()))?;
+        for line in content.lines() {
+            if line.starts_with('#') || line.starts_with('^') {
+                continue;
+            }
+            if let Some((commit_id, name)) = line.split_once(' ') {
+                if name.trim() == reference {
+                    return Ok(commit_id.to_string());
+                }
+            }
+        }
+    }
+    Err(anyhow!(format!("git ref: {} not found, is the branch empty?", reference)))
+}
+
+fn read_trimmed(path: &Path) -> anyhow::Result<String> {
+    Ok(std::fs::read_to_string(path).context(format!("failed to read: {}", path.display()))?.trim().to_string())
+}
+
+/// sha1 or sha256 object name
+fn is_commit_id(id: &str) -> bool {
+    (id.len() == 40 || id.len() == 64) && id.chars().all(|c| { c.is_ascii_hexdigit() })
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use crate::excel::revision::git_commit_id;
+
+    #[test]
+    fn test_git_commit_id() -> anyhow::Result<()> {
+        let commit_a = "a".repeat(40);
+        let commit_b = "b".repeat(40);
+        let repo = env::temp_dir().join("test_git_commit_id");
+        if repo.exists() {
+            std::fs::remove_dir_all(&repo)?;
+        }
+        let excel = repo.join("excel");
+        std::fs::create_dir_all(&excel)?;
+        std::fs::create_dir_all(repo.join(".git/refs/heads"))?;
+        std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/main\n")?;
+        std::fs::write(repo.join(".git/packed-refs"), format!("# pack-refs with: peeled\n{} refs/heads/main\n", commit_a))?;
+        assert_eq!(git_commit_id(&excel)?, commit_a);
+
+        std::fs::write(repo.join(".git/refs/heads/main"), format!("{}\n", commit_b))?;
+        assert_eq!(git_commit_id(&excel)?, commit_b);
+
+        std::fs::write(repo.join(".git/HEAD"), format!("{}\n", commit_a))?;
+        assert_eq!(git_commit_id(&excel)?, commit_a);
+
+        std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/empty\n")?;
+        assert!(git_commit_id(&excel).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use convert_case::{Case, Casing};
+
+use crate::excel::enum_define::EnumDefines;
+use crate::excel::excel_define::{CellType, GameConfig, GameConfigs};
+
+const KEYWORDS: [&str; 50] = [
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while", "async", "await", "dyn", "abstract", "become",
+    "box", "do", "final", "macro", "override", "priv", "typeof", "unsized", "virtual", "yield",
+];
+
+/// generate rust code of typed config structs from the excel header rows
+pub struct RustWriter;
+
+impl RustWriter {
+    /// `mod.rs` declaring one module per sheet and a `GameTables` holding all of them, and `enums` if there are enums
+    pub fn write_mod(game_configs: &GameConfigs, enums: &EnumDefines) -> anyhow::Result<String> {
+        let mut modules = HashMap::new();
+        let mut names = vec![];
+        for config in &game_configs.data {
+            let module = rust_ident(&config.name, Case::Snake)?;
+            if let Some(other) = modules.insert(module.clone(), config.name.clone()) {
+                return Err(anyhow!(format!("config {} and {} generate the same rust module: {}", other, config.name, module)));
+            }
+            names.push((module, table_name(config)?));
+        }
+        names.sort();
+        let mut formatted_mod = vec![];
+        let mut formatted_field = vec![];
+        let mut formatted_load = vec![];
+        if !enums.is_empty() {
+            if let Some(config) = modules.get("enums") {
+                return Err(anyhow!(format!("config {} and the enum constants generate the same rust module: enums", config)));
+            }
+            formatted_mod.push("pub mod enums;".to_string());
+        }
+        for (module, table) in names {
+            formatted_mod.push(format!("pub mod {};", module));
+            formatted_field.push(format!("    pub {}: {}::{},", module, module, table));
+            formatted_load.push(format!("            {}: {}::{}::load(tables.table({}::{}::NAME)?.config())?,", module, module, table, module, table));
+        }
+        Ok(format!(r#"
+// generated by excel_tool, do not edit
+use common::excel::config_loader::ConfigTables;
+
+{}
+
+#[derive(Debug, Clone, Default)]
+pub struct GameTables {{
+{}
+}}
+
+impl GameTables {{
+    pub fn load(tables: &ConfigTables) -> anyhow::Result<Self> {{
+        Ok(Self {{
+{}
+        }})
+    }}
+}}
+        "#, formatted_mod.join("\n"), formatted_field.join("\n"), formatted_load.join("\n")).trim().to_string() + "\n")
+    }
+
+    /// one struct per row and a table indexed by the side key columns, composite keys become tuples
+    pub fn write_config(config: &GameConfig) -> anyhow::Result<String> {
+        let key_indexes = config.side_key_indexes();
+        if key_indexes.is_empty() {
+            return Err(anyhow!(format!("{} {} key not found", config.name, config.side)));
+        }
+        let mut key_fields = vec![];
+        let mut key_types = vec![];
+        for index in &key_indexes {
+            let key_type = &config.cell_type[*index];
+            if !key_type.hashable() {
+                return Err(anyhow!(format!("{} key column {} type {} cannot be used as a key", config.name, config.cell_name[*index], key_type)));
+            }
+            if config.nullable(*index) {
+                return Err(anyhow!(format!("{} key column {} cannot be nullable", config.name, config.cell_name[*index])));
+            }
+            key_fields.push(format!("value.{}.clone()", rust_ident(&config.cell_name[*index], Case::Snake)?));
+            key_types.push(key_type.rust_type().unwrap_or_default());
+        }
+        let (key_expr, key_rust_type) = if key_indexes.len() == 1 {
+            (key_fields.join(""), key_types.join(""))
+        } else {
+            (format!("({})", key_fields.join(", ")), format!("({})", key_types.join(", ")))
+        };
+        let row_name = format!("{}Config", rust_ident(&config.name, Case::UpperCamel)?);
+        let table_name = table_name(config)?;
+        let mut fields = HashMap::new();
+        let mut structs = vec![];
+        // generated structs must not clash with each other or with the row and table structs
+        let mut struct_names = HashSet::from([row_name.clone(), table_name.clone()]);
+        let mut formatted_field = vec![];
+        let mut formatted_index = vec![];
+        let mut formatted_parse = vec![];
+        for (j, (name, ty)) in config.cell_name.iter().zip(&config.cell_type).enumerate() {
+            let field = rust_ident(name, Case::Snake)?;
+            if let Some(other) = fields.insert(field.clone(), name.clone()) {
+                return Err(anyhow!(format!("{} column {} and {} generate the same rust field: {}", config.name, other, name, field)));
+            }
+            let index = field.trim_start_matches("r#");
+            let struct_name = format!("{}{}", rust_ident(&config.name, Case::UpperCamel)?, rust_ident(name, Case::UpperCamel)?);
+            let rust_type = field_type(ty, &struct_name, &mut structs, &mut struct_names).map_err(|error| { anyhow!(format!("{} column {}: {}", config.name, name, error)) })?;
+            formatted_index.push(format!("        let {}_index = config.column_index({:?}, &{})?;", index, name, cell_type_expr(ty)));
+            let parse = if ty.is_composite() {
+                format!("common::excel::composite::parse_composite(&config.cell_type[{}_index], &row[{}_index]).context(format!(\"{{}} row {{}} column {{}}\", Self::NAME, i + 1, {:?}))?", index, index, name)
+            } else {
+                format!("row[{}_index].parse().context(format!(\"{{}} row {{}} column {{}}\", Self::NAME, i + 1, {:?}))?", index, name)
+            };
+            // empty cells of nullable columns are left empty by the export
+            if config.nullable(j) {
+                formatted_field.push(format!("    pub {}: Option<{}>,", field, rust_type));
+                formatted_parse.push(format!("                {}: if row[{}_index].is_empty() {{ None }} else {{ Some({}) }},", field, index, parse));
+            } else {
+                formatted_field.push(format!("    pub {}: {},", field, rust_type));
+                formatted_parse.push(format!("                {}: {},", field, parse));
+            }
+        }
+        let formatted_structs = structs.iter().map(|s| { format!("{}\n\n", s) }).collect::<String>();
+        Ok(format!(r#"
+// generated by excel_tool from sheet {:?}, do not edit
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use anyhow::Context;
+
+use common::excel::convert::*;
+use common::excel::excel_define::{{CellType, GameConfig}};
+
+{formatted_structs}#[derive(Debug, Clone, PartialEq)]
+pub struct {row_name} {{
+{}
+}}
+
+#[derive(Debug, Clone, Default)]
+pub struct {table_name} {{
+    rows: Vec<{row_name}>,
+    key_to_index: HashMap<{key_rust_type}, usize>,
+}}
+
+impl {table_name} {{
+    pub const NAME: &'static str = {:?};
+
+    pub fn load(config: &GameConfig) -> anyhow::Result<Self> {{
+{}
+        let mut rows = Vec::with_capacity(config.data.len());
+        let mut key_to_index = HashMap::with_capacity(config.data.len());
+        for (i, row) in config.data.iter().enumerate() {{
+            let value = {row_name} {{
+{}
+            }};
+            let key = {key_expr};
+            if key_to_index.contains_key(&key) {{
+                return Err(anyhow::anyhow!(format!("{{}} row {{}} duplicate key: {{:?}}", Self::NAME, i + 1, key)));
+            }}
+            key_to_index.insert(key, i);
+            rows.push(value);
+        }}
+        Ok(Self {{ rows, key_to_index }})
+    }}
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&{row_name}> where {key_rust_type}: Borrow<Q>, Q: Hash + Eq + ?Sized {{
+        self.key_to_index.get(key).map(|i| {{ &self.rows[*i] }})
+    }}
+
+    pub fn rows(&self) -> &[{row_name}] {{
+        &self.rows
+    }}
+
+    pub fn len(&self) -> usize {{
+        self.rows.len()
+    }}
+
+    pub fn is_empty(&self) -> bool {{
+        self.rows.is_empty()
+    }}
+}}
+        "#, config.name, formatted_field.join("\n"), config.name, formatted_index.join("\n"), formatted_parse.join("\n")).trim().to_string() + "\n")
+    }
+
+    /// `enums.rs`, a struct per enum with its members as constants
+    pub fn write_enums(enums: &EnumDefines) -> anyhow::Result<String> {
+        let mut idents = HashMap::new();
+        let mut formatted_enums = vec![];
+        for define in enums.enums.values() {
+            let ident = rust_ident(&define.name, Case::UpperCamel)?;
+            if let Some(other) = idents.insert(ident.clone(), define.name.clone()) {
+                return Err(anyhow!(format!("enum {} and {} generate the same rust struct: {}", other, define.name, ident)));
+            }
+            let mut constants = HashMap::new();
+            let mut formatted_const = vec![];
+            let mut formatted_name = vec![];
+            for member in &define.members {
+                let constant = rust_ident(&member.name, Case::UpperSnake)?;
+                if let Some(other) = constants.insert(constant.clone(), member.name.clone()) {
+                    return Err(anyhow!(format!("enum {} member {} and {} generate the same rust constant: {}", define.name, other, member.name, constant)));
+                }
+                formatted_const.push(format!("    pub const {}: i32 = {};", constant, member.value));
+                formatted_name.push(format!("            {} => Some({:?}),", member.value, member.name));
+            }
+            formatted_enums.push(format!(r#"
+/// enum {} defined in {}
+pub struct {};
+
+impl {} {{
+{}
+
+    pub fn name(value: i32) -> Option<&'static str> {{
+        match value {{
+{}
+            _ => None,
+        }}
+    }}
+}}
+            "#, define.name, define.source, ident, ident, formatted_const.join("\n"), formatted_name.join("\n")).trim().to_string());
+        }
+        Ok(format!("// generated by excel_tool, do not edit\n\n{}\n", formatted_enums.join("\n\n")))
+    }
+}
+
+/// rust type of a column or struct field, a struct named `name` is added to `structs` for every struct type,
+/// `names` holds the struct names already taken
+fn field_type(ty: &CellType, name: &str, structs: &mut Vec<String>, names: &mut HashSet<String>) -> anyhow::Result<String> {
+    match ty {
+        CellType::Array(element) => Ok(format!("Vec<{}>", field_type(element, name, structs, names)?)),
+        CellType::Struct(fields) => {
+            let mut formatted_field = vec![];
+            for field in fields {
+                let ident = rust_ident(&field.name, Case::Snake)?;
+                let field_type = field_type(&field.ty, &format!("{}{}", name, rust_ident(&field.name, Case::UpperCamel)?), structs, names)?;
+                // composite cells are deserialized by the field names of the cell type
+                if ident.trim_start_matches("r#") != field.name {
+                    formatted_field.push(format!("    #[serde(rename = {:?})]", field.name));
+                }
+                formatted_field.push(format!("    pub {}: {},", ident, field_type));
+            }
+            if !names.insert(name.to_string()) {
+                return Err(anyhow!(format!("rust struct {} is generated twice, rename the column or the struct field", name)));
+            }
+            structs.push(format!("#[derive(Debug, Clone, PartialEq, serde::Deserialize)]\npub struct {} {{\n{}\n}}", name, formatted_field.join("\n")));
+            Ok(name.to_string())
+        }
+        other => other.rust_type().map(ToString::to_string).ok_or_else(|| { anyhow!(format!("{} has no rust type", other)) }),
+    }
+}
+
+/// the expression of a cell type in generated code
+fn cell_type_expr(ty: &CellType) -> String {
+    match ty {
+        CellType::Enum(name) => format!("CellType::Enum({:?}.to_string())", name),
+        CellType::Flags(name) => format!("CellType::Flags({:?}.to_string())", name),
+        CellType::Array(_) | CellType::Struct(_) => format!("CellType::parse_type({:?})?", ty.to_string()),
+        other => format!("CellType::{:?}", other),
+    }
+}
+
+fn table_name(config: &GameConfig) -> anyhow::Result<String> {
+    Ok(format!("{}Table", rust_ident(&config.name, Case::UpperCamel)?))
+}
+
+/// convert a sheet or column name to a rust identifier, raw identifiers are used for keywords
+pub fn rust_ident(name: &str, case: Case) -> anyhow::Result<String> {
+    let ident = name.to_case(case);
+    let valid = ident.chars().next().map(|c| { c.is_ascii_alphabetic() || c == '_' }).unwrap_or(false) && ident.chars().all(|c| { c.is_ascii_alphanumeric() || c == '_' });
+    if !valid {
+        return Err(anyhow!(format!("{} cannot be converted to a rust identifier, got: {}", name, ident)));
+    }
+    if ["self", "Self", "super", "crate"].contains(&ident.as_str()) {
+        return Err(anyhow!(format!("{} is a reserved rust identifier", name)));
+    }
+    if KEYWORDS.contains(&ident.as_str()) {
+        Ok(format!("r#{}", ident))
+    } else {
+        Ok(ident)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use convert_case::Case;
+
+    use crate::excel::excel_define::{CellRule, CellType, GameConfig, KeyType};
+    use crate::excel::rust_writer::{rust_ident, RustWriter};
+
+    #[test]
+    fn test_write_config() -> anyhow::Result<()> {
+        assert_eq!(rust_ident("itemType", Case::Snake)?, "item_type");
+        assert_eq!(rust_ident("type", Case::Snake)?, "r#type");
+        assert!(rust_ident("1st", Case::Snake).is_err());
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "type".to_string(), "dropRate".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::Server])
+            .cell_type(vec![CellType::UInt, CellType::Int, CellType::DictionaryStringFloat])
+            .data(vec![])
+            .build();
+        let code = RustWriter::write_config(&config)?;
+        assert!(code.contains("pub struct ItemConfig {"));
+        assert!(code.contains("pub r#type: i32,"));
+        assert!(code.contains("pub drop_rate: DictionaryStringFloat,"));
+        assert!(code.contains("key_to_index: HashMap<u32, usize>,"));
+
+        let mut config = config;
+        config.cell_rule = vec![vec![], vec![CellRule::Nullable], vec![]];
+        let code = RustWriter::write_config(&config)?;
+        assert!(code.contains("pub r#type: Option<i32>,"));
+        assert!(code.contains("r#type: if row[type_index].is_empty() { None } else { Some(row[type_index].parse()"));
+
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "rewards".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::parse_type("array<struct{itemId:uint,count:int}>")?])
+            .data(vec![])
+            .build();
+        let code = RustWriter::write_config(&config)?;
+        assert!(code.contains("pub struct ItemRewards {\n    #[serde(rename = \"itemId\")]\n    pub item_id: u32,\n    pub count: i32,\n}"));
+        assert!(code.contains("pub rewards: Vec<ItemRewards>,"));
+        assert!(code.contains("CellType::parse_type(\"array<struct{itemId:uint,count:int}>\")?"));
+
+        let config = GameConfig::builder()
+            .name("item".to_string())
+            .cell_name(vec!["id".to_string(), "config".to_string(), "reward_item".to_string(), "reward".to_string()])
+            .key_type(vec![KeyType::AllKey, KeyType::All, KeyType::All, KeyType::All])
+            .cell_type(vec![CellType::UInt, CellType::parse_type("array<int>")?, CellType::parse_type("struct{id:uint}")?, CellType::parse_type("struct{item:struct{id:uint}}")?])
+            .data(vec![])
+            .build();
+        assert!(RustWriter::write_config(&config).unwrap_err().to_string().contains("item column reward: rust struct ItemRewardItem is generated twice"));
+        let mut config = config;
+        config.cell_type[1] = CellType::parse_type("struct{id:uint}")?;
+        assert!(RustWriter::write_config(&config).unwrap_err().to_string().contains("item column config: rust struct ItemConfig is generated twice"));
+        Ok(())
+    }
+}
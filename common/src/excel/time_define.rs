@@ -0,0 +1,298 @@
+use std::ops::RangeInclusive;
+
+use anyhow::anyhow;
+use chrono::{Datelike, DateTime, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+
+use crate::excel::excel_define::{CellType, GameConfig};
+
+/// `+08:00`, `-05:30`, `UTC` or `Z`, the timezone datetime cells without an offset are written in
+pub fn parse_timezone(s: &str) -> anyhow::Result<FixedOffset> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Ok(utc_offset());
+    }
+    let error = || { anyhow!(format!("incorrect timezone: {}, expect an offset like +08:00", s)) };
+    let (sign, rest) = match s.chars().next() {
+        Some('+') => (1, &s[1..]),
+        Some('-') => (-1, &s[1..]),
+        _ => return Err(error()),
+    };
+    let (hours, minutes) = rest.split_once(":").unwrap_or((rest, "0"));
+    let hours: i32 = hours.parse().map_err(|_| { error() })?;
+    let minutes: i32 = minutes.parse().map_err(|_| { error() })?;
+    if minutes >= 60 {
+        return Err(error());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(error)
+}
+
+pub fn utc_offset() -> FixedOffset {
+    FixedOffset::east_opt(0).expect("zero offset is valid")
+}
+
+/// unix seconds a datetime cell may have, 1973-03-03 to 2286-11-20, so a date typed as a number like `20230312` is
+/// not taken as unix seconds
+pub const DATETIME_SECONDS: RangeInclusive<i64> = 100_000_000..=9_999_999_999;
+
+/// unix seconds of a datetime cell, `2023-03-12 10:00:00`, `2023-03-12 10:00` and `2023-03-12` are in `timezone`,
+/// rfc3339 like `2023-03-12T10:00:00+08:00` carries its own offset and a plain integer is already unix seconds
+pub fn parse_datetime(data: &str, timezone: &FixedOffset) -> anyhow::Result<i64> {
+    let data = data.trim();
+    let seconds = parse_seconds(data, timezone)?;
+    if !DATETIME_SECONDS.contains(&seconds) {
+        return Err(anyhow!(format!("incorrect datetime: {}, unix seconds {} out of {}..={}, write dates as yyyy-mm-dd hh:mm:ss", data, seconds, DATETIME_SECONDS.start(), DATETIME_SECONDS.end())));
+    }
+    Ok(seconds)
+}
+
+fn parse_seconds(data: &str, timezone: &FixedOffset) -> anyhow::Result<i64> {
+    if let Ok(seconds) = data.parse::<i64>() {
+        return Ok(seconds);
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(data) {
+        return Ok(datetime.timestamp());
+    }
+    let naive = NaiveDateTime::parse_from_str(data, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| { NaiveDateTime::parse_from_str(data, "%Y-%m-%d %H:%M") })
+        .ok()
+        .or_else(|| { NaiveDate::parse_from_str(data, "%Y-%m-%d").ok().and_then(|date| { date.and_hms_opt(0, 0, 0) }) })
+        .ok_or_else(|| { anyhow!(format!("incorrect datetime: {}, expect yyyy-mm-dd hh:mm:ss", data)) })?;
+    match timezone.from_local_datetime(&naive) {
+        LocalResult::Single(datetime) => Ok(datetime.timestamp()),
+        _ => Err(anyhow!(format!("datetime {} does not exist in timezone {}", data, timezone))),
+    }
+}
+
+/// seconds of a duration cell, units are `d`, `h`, `m` and `s`, e.g. `1h30m`, a plain integer is seconds
+pub fn parse_duration(data: &str) -> anyhow::Result<u64> {
+    let data = data.trim();
+    if let Ok(seconds) = data.parse::<u64>() {
+        return Ok(seconds);
+    }
+    let error = || { anyhow!(format!("incorrect duration: {}, expect a number of seconds or units like 1d2h30m10s", data)) };
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    let mut last_unit = u64::MAX;
+    for c in data.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(error()),
+        };
+        // every unit once, from the largest to the smallest
+        if number.is_empty() || unit >= last_unit {
+            return Err(error());
+        }
+        let value: u64 = number.parse().map_err(|_| { error() })?;
+        seconds = value.checked_mul(unit).and_then(|value| { seconds.checked_add(value) }).ok_or_else(error)?;
+        number.clear();
+        last_unit = unit;
+    }
+    if !number.is_empty() || last_unit == u64::MAX {
+        return Err(error());
+    }
+    Ok(seconds)
+}
+
+/// a cron like schedule of `minute hour day month weekday`, fields take `*`, values, ranges `1-5`, lists `1,3` and
+/// steps `*/15` or `0-30/10`, weekdays count from 0 for sunday, `@hourly`, `@daily`, `@weekly` and `@monthly` are
+/// shortcuts. as in cron a time matches if either the day or the weekday matches when both are restricted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    day_restricted: bool,
+    weekday_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> anyhow::Result<Self> {
+        let expression = expression.trim();
+        let expanded = match expression {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(anyhow!(format!("incorrect cron: {}, expect 5 fields of minute hour day month weekday", expression)));
+        }
+        let field = |i: usize, min: u32, max: u32| -> anyhow::Result<u64> {
+            cron_field(fields[i], min, max).map_err(|error| { anyhow!(format!("incorrect cron: {}, {}", expression, error)) })
+        };
+        let mut weekdays = field(4, 0, 7)?;
+        // 7 is sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            expression: expression.to_string(),
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            weekdays,
+            // `*/2` is unrestricted too, cron only checks the first character
+            day_restricted: !fields[2].starts_with('*'),
+            weekday_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    fn matches_day(&self, date: &NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.day_restricted && self.weekday_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// the first time strictly after `time` in its timezone, none for an empty schedule or one that never matches
+    pub fn next_after<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        if self.expression.is_empty() {
+            return None;
+        }
+        let local = time.naive_local();
+        let start = local.date().and_hms_opt(local.hour(), local.minute(), 0)? + Duration::minutes(1);
+        let mut date = start.date();
+        // leap days repeat every 4 years, later times never match
+        for _ in 0..366 * 4 + 1 {
+            if self.matches_day(&date) {
+                let first_minute = if date == start.date() { start.hour() * 60 + start.minute() } else { 0 };
+                for minute_of_day in first_minute..24 * 60 {
+                    let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                    if self.hours & (1 << hour) == 0 || self.minutes & (1 << minute) == 0 {
+                        continue;
+                    }
+                    // local times skipped by a daylight saving change do not happen
+                    match time.timezone().from_local_datetime(&date.and_hms_opt(hour, minute, 0)?) {
+                        LocalResult::Single(next) | LocalResult::Ambiguous(next, _) => return Some(next),
+                        LocalResult::None => continue,
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+/// bits of the values a cron field matches
+fn cron_field(field: &str, min: u32, max: u32) -> anyhow::Result<u64> {
+    let mut bits = 0;
+    for part in field.split(",") {
+        let (range, step) = match part.split_once("/") {
+            None => (part, 1),
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| { *step > 0 }).ok_or_else(|| { anyhow!(format!("incorrect step: {}", part)) })?),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once("-") {
+            (start.parse()?, end.parse()?)
+        } else {
+            let value = range.parse()?;
+            // `5/10` runs from 5 to the end like cron does
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return Err(anyhow!(format!("{} out of {}-{}", part, min, max)));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// replace the datetime cells of the config with unix seconds and the duration cells with seconds
+pub fn resolve_config(config: &mut GameConfig, timezone: &FixedOffset) -> Vec<anyhow::Error> {
+    let mut errors = vec![];
+    for j in 0..config.cell_type.len() {
+        let ty = config.cell_type[j].clone();
+        if !matches!(ty, CellType::DateTime | CellType::Duration) {
+            continue;
+        }
+        for i in 0..config.data.len() {
+            if config.data[i][j].is_empty() {
+                continue;
+            }
+            let resolved = match ty {
+                CellType::DateTime => parse_datetime(&config.data[i][j], timezone).map(|seconds| { seconds.to_string() }),
+                _ => parse_duration(&config.data[i][j]).map(|seconds| { seconds.to_string() }),
+            };
+            match resolved {
+                Ok(value) => config.data[i][j] = value,
+                Err(error) => errors.push(config.cell_error(i, j, error)),
+            }
+        }
+    }
+    errors
+}
+
+/// a resolved datetime cell, unresolved cells are read in utc
+pub fn datetime_of(data: &str) -> anyhow::Result<DateTime<Utc>> {
+    let seconds = parse_datetime(data, &utc_offset())?;
+    Utc.timestamp_opt(seconds, 0).single().ok_or_else(|| { anyhow!(format!("datetime {} out of range", data)) })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::excel::time_define::{CronSchedule, parse_datetime, parse_duration, parse_timezone};
+
+    #[test]
+    fn test_time_cells() -> anyhow::Result<()> {
+        let china = parse_timezone("+08:00")?;
+        assert_eq!(parse_datetime("2023-03-12 08:00:00", &china)?, 1678579200);
+        assert_eq!(parse_datetime("2023-03-12", &parse_timezone("UTC")?)?, 1678579200);
+        assert_eq!(parse_datetime("2023-03-12T00:00:00Z", &china)?, 1678579200);
+        assert_eq!(parse_datetime("1678579200", &china)?, 1678579200);
+        assert!(parse_datetime("2023-02-30", &china).is_err());
+        assert!(parse_datetime("20230312", &china).unwrap_err().to_string().contains("out of 100000000..=9999999999"));
+        assert!(parse_datetime("1970-01-02", &china).is_err());
+        assert!(parse_timezone("08:00").is_err());
+
+        assert_eq!(parse_duration("1h30m")?, 5400);
+        assert_eq!(parse_duration("1d10s")?, 86410);
+        assert_eq!(parse_duration("90")?, 90);
+        assert!(parse_duration("30m1h").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("h").is_err());
+
+        let refresh = CronSchedule::parse("0 5 * * 1-5")?;
+        let friday = china.with_ymd_and_hms(2023, 3, 10, 5, 0, 0).unwrap();
+        assert_eq!(refresh.next_after(&friday), Some(china.with_ymd_and_hms(2023, 3, 13, 5, 0, 0).unwrap()));
+        let quarter = CronSchedule::parse("*/15 * * * *")?;
+        assert_eq!(quarter.next_after(&Utc.with_ymd_and_hms(2023, 3, 12, 23, 50, 30).unwrap()), Some(Utc.with_ymd_and_hms(2023, 3, 13, 0, 0, 0).unwrap()));
+        let leap = CronSchedule::parse("0 0 29 2 *")?;
+        assert_eq!(leap.next_after(&Utc.with_ymd_and_hms(2023, 3, 1, 0, 0, 0).unwrap()), Some(Utc.with_ymd_and_hms(2024, 2, 29, 0, 0, 0).unwrap()));
+        assert_eq!(CronSchedule::parse("0 0 31 2 *")?.next_after(&friday), None);
+        // friday the 10th is even, so only the next monday matches
+        let odd_monday = CronSchedule::parse("0 0 */2 * 1")?;
+        assert_eq!(odd_monday.next_after(&friday), Some(china.with_ymd_and_hms(2023, 3, 13, 0, 0, 0).unwrap()));
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("0 0 * *").is_err());
+        Ok(())
+    }
+}
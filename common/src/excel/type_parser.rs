@@ -0,0 +1,168 @@
+use anyhow::anyhow;
+
+use crate::excel::excel_define::{CellType, NAMED_TYPES, StructField};
+
+/// recursive descent parser of the cell type grammar, whitespace between tokens is ignored
+///
+/// ```text
+/// type  = name | "vector" "[" type ("," type)* "]" | ("enum" | "flags") "[" name "]"
+///       | "array" "<" type ">" | "struct" "{" field ("," field)* "}"
+/// field = name ":" type
+/// ```
+///
+/// `vector[..]` is one of the fixed vector types, arrays and structs nest but only hold scalars, arrays and structs
+pub struct TypeParser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> TypeParser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    pub fn parse(mut self) -> anyhow::Result<CellType> {
+        let ty = self.parse_type()?;
+        self.skip_whitespace();
+        if self.pos < self.chars.len() {
+            return Err(self.error("unexpected character"));
+        }
+        Ok(ty)
+    }
+
+    fn parse_type(&mut self) -> anyhow::Result<CellType> {
+        let name = self.name()?;
+        match name.as_str() {
+            "array" => {
+                self.expect('<')?;
+                let element = self.nested_type()?;
+                self.expect('>')?;
+                Ok(CellType::Array(Box::new(element)))
+            }
+            "struct" => {
+                self.expect('{')?;
+                let mut fields: Vec<StructField> = vec![];
+                loop {
+                    let name = self.name()?;
+                    if fields.iter().any(|field| { field.name == name }) {
+                        return Err(self.error(format!("duplicate struct field {}", name)));
+                    }
+                    self.expect(':')?;
+                    let ty = self.nested_type()?;
+                    fields.push(StructField { name, ty });
+                    if !self.eat(',') {
+                        break;
+                    }
+                }
+                self.expect('}')?;
+                Ok(CellType::Struct(fields))
+            }
+            "enum" | "flags" => {
+                self.expect('[')?;
+                let enum_name = self.name()?;
+                self.expect(']')?;
+                Ok(if name == "enum" { CellType::Enum(enum_name) } else { CellType::Flags(enum_name) })
+            }
+            "vector" => {
+                self.expect('[')?;
+                let mut components = vec![self.parse_type()?];
+                while self.eat(',') {
+                    components.push(self.parse_type()?);
+                }
+                self.expect(']')?;
+                let vector = format!("vector[{}]", components.iter().map(ToString::to_string).collect::<Vec<_>>().join(","));
+                named_type(&vector).ok_or_else(|| { self.error(format!("unknown vector type {}", vector)) })
+            }
+            other => named_type(other).ok_or_else(|| { self.error(format!("unknown type {}", other)) }),
+        }
+    }
+
+    /// element of an array or field of a struct
+    fn nested_type(&mut self) -> anyhow::Result<CellType> {
+        let start = self.pos;
+        let ty = self.parse_type()?;
+        if !ty.is_composite() && !ty.is_composite_scalar() {
+            self.pos = start;
+            return Err(self.error(format!("{} cannot be nested in array or struct", ty)));
+        }
+        Ok(ty)
+    }
+
+    fn name(&mut self) -> anyhow::Result<String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.pos < self.chars.len() && (self.chars[self.pos].is_ascii_alphanumeric() || self.chars[self.pos] == '_') {
+            self.pos += 1;
+        }
+        if start == self.pos || self.chars[start].is_ascii_digit() {
+            self.pos = start;
+            return Err(self.error("expect a name"));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> anyhow::Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expect {}", c)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: impl ToString) -> anyhow::Error {
+        anyhow!(format!("incorrect cell type: {}, {} at column {}", self.source, message.to_string(), self.pos + 1))
+    }
+}
+
+fn named_type(name: &str) -> Option<CellType> {
+    NAMED_TYPES.iter().find(|(n, _)| { *n == name }).map(|(_, ty)| { ty.clone() })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::excel::excel_define::{CellType, StructField};
+
+    #[test]
+    fn test_parse_type() -> anyhow::Result<()> {
+        assert_eq!(CellType::parse_type("uint")?, CellType::UInt);
+        assert_eq!(CellType::parse_type("vector2_int")?, CellType::Vector2Int);
+        assert_eq!(CellType::parse_type("vector[ int, int ]")?, CellType::Vector2Int);
+        assert_eq!(CellType::parse_type("enum[Quality]")?, CellType::Enum("Quality".to_string()));
+        let reward = CellType::parse_type("array<struct{ id:uint, count:int, weight:array<float> }>")?;
+        assert_eq!(reward, CellType::Array(Box::new(CellType::Struct(vec![
+            StructField { name: "id".to_string(), ty: CellType::UInt },
+            StructField { name: "count".to_string(), ty: CellType::Int },
+            StructField { name: "weight".to_string(), ty: CellType::Array(Box::new(CellType::Float)) },
+        ]))));
+        assert_eq!(reward.to_string(), "array<struct{id:uint,count:int,weight:array<float>}>");
+        assert_eq!(CellType::parse_type(&reward.to_string())?, reward);
+        assert_eq!(CellType::Vector2Int.to_string(), "vector[int,int]");
+        assert_eq!(CellType::parse_type("array<vector2_int>").unwrap_err().to_string(), "incorrect cell type: array<vector2_int>, vector[int,int] cannot be nested in array or struct at column 7");
+        assert!(CellType::parse_type("struct{id:uint,id:int}").is_err());
+        assert!(CellType::parse_type("vector[int,bool]").is_err());
+        assert!(CellType::parse_type("array<uint").is_err());
+        assert!(CellType::parse_type("uint int").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use calamine::{Cell, DataType, open_workbook_auto, Range, Reader};
+use tracing::{info, warn};
+
+use crate::excel::excel_define::{CellRef, CellRule, CellType, GameConfig, KeyType};
+use crate::excel::report::{column_letter, ErrorReport, ExcelError};
+
+/// sheets whose name starts with this are scratch sheets and not exported
+pub const IGNORE_SHEET_PREFIX: &str = "#";
+/// cells of the 4th header row that start with this declare column rules, e.g. `rules:unique;range:1..100`,
+/// the other cells are notes as the row used to be
+pub const RULES_MARKER: &str = "rules:";
+
+/// format of an input file, detected from its extension, all of them use the same header rows
+#[derive(strum::EnumString, strum::Display, strum::EnumIter, Debug, PartialEq, Eq, Clone, Copy)]
+#[strum(serialize_all = "snake_case", ascii_case_insensitive)]
+pub enum InputFormat {
+    Xlsx,
+    Xlsm,
+    Xlsb,
+    Xls,
+    Ods,
+    /// one sheet named after the file, utf-8 with `,` separated cells
+    Csv,
+}
+
+impl InputFormat {
+    pub fn detect(path: &Path) -> Option<InputFormat> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+/// read every sheet of the workbook, sheet errors are collected into the report so the other sheets are still checked
+pub fn read_workbook(path: PathBuf, report: &mut ErrorReport) -> anyhow::Result<Vec<GameConfig>> {
+    let display_path = path.display().to_string();
+    info!("read: {}", display_path);
+    let sheets = match InputFormat::detect(&path) {
+        None => return Err(anyhow!(format!("unsupported input format: {}", display_path))),
+        Some(InputFormat::Csv) => {
+            let sheet_name = path.file_stem().map(|stem| { stem.to_string_lossy().to_string() }).unwrap_or_default();
+            vec![(sheet_name, read_csv(&path).context(format!("open csv: {} failed", display_path))?)]
+        }
+        Some(_) => open_workbook_auto(path).context(format!("open excel: {} failed", display_path))?.worksheets(),
+    };
+    let mut configs = vec![];
+    for (sheet_name, data) in sheets {
+        if sheet_name.starts_with(IGNORE_SHEET_PREFIX) {
+            info!("ignore sheet: {} [{}]", display_path, sheet_name);
+            continue;
+        }
+        match read_sheet(&display_path, &sheet_name, &data) {
+            Ok(Some(config)) => {
+                configs.push(config);
+            }
+            Ok(None) => {}
+            Err(error) if error.is::<ExcelError>() => {
+                report.push(error);
+            }
+            Err(error) => {
+                report.push(ExcelError::sheet(&display_path, &sheet_name, format!("{:#}", error)).into());
+            }
+        }
+    }
+    Ok(configs)
+}
+
+/// cells of a csv file as strings, rows may have different lengths
+fn read_csv(path: &Path) -> anyhow::Result<Range<DataType>> {
+    let mut content = std::fs::read(path)?;
+    // every record ends with a line break, so the lines a record takes are counted the same way for the last one
+    if !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(content.as_slice());
+    let mut record = csv::StringRecord::new();
+    let mut cells = vec![];
+    let mut row = 0;
+    loop {
+        let start = reader.position().line();
+        if !reader.read_record(&mut record)? {
+            break;
+        }
+        // blank lines are skipped by the reader, they are the empty header rows of a single column sheet
+        let lines = 1 + record.iter().map(|value| { value.matches('\n').count() as u64 }).sum::<u64>();
+        row += (reader.position().line() - start).saturating_sub(lines) as usize;
+        for (j, value) in record.iter().enumerate() {
+            if !value.is_empty() {
+                cells.push(Cell::new((row as u32, j as u32), DataType::String(value.to_string())));
+            }
+        }
+        row += 1;
+    }
+    Ok(Range::from_sparse(cells))
+}
+
+/// header rows are column name, cell type, key type, column rules or notes and a free comment row, data starts at the 6th row
+pub fn read_sheet(display_path: &str, sheet_name: &str, data: &Range<DataType>) -> anyhow::Result<Option<GameConfig>> {
+    let (start_row, start_column) = data.start().map(|(row, column)| { (row as usize, column as usize) }).unwrap_or((0, 0));
+    let header_error = |i: usize, j: usize, message: String| -> anyhow::Error {
+        ExcelError {
+            row: Some(start_row + i + 1),
+            column: Some(column_letter(start_column + j + 1)),
+            ..ExcelError::sheet(display_path, sheet_name, message)
+        }.into()
+    };
+    let mut cell_name = vec![];
+    let mut cell_type = vec![];
+    let mut cell_ref = vec![];
+    let mut cell_rule = vec![];
+    let mut key_type = vec![];
+    let mut excel_data = vec![];
+    let mut row_number = vec![];
+    for (i, row) in data.rows().enumerate() {
+        let mut row_data = vec![];
+        for (j, data_type) in row.iter().enumerate() {
+            match i {
+                0 => {
+                    match data_type {
+                        DataType::String(data) => {
+                            cell_name.push(data.trim().to_string());
+                        }
+                        other => {
+                            return Err(header_error(i, j, format!("excel string expected, got: {}", other)));
+                        }
+                    }
+                }
+                1 => {
+                    match data_type {
+                        DataType::String(data) => {
+                            let (ty, annotation) = match data.split_once("|") {
+                                None => (data.as_str(), None),
+                                Some((ty, annotation)) => (ty, Some(CellRef::from_str(annotation).map_err(|e| { header_error(i, j, e.to_string()) })?)),
+                            };
+                            cell_type.push(CellType::parse_type(ty).map_err(|e| { header_error(i, j, e.to_string()) })?);
+                            cell_ref.push(annotation);
+                        }
+                        other => {
+                            return Err(header_error(i, j, format!("excel string expected, got: {}", other)));
+                        }
+                    }
+                }
+                2 => {
+                    match data_type {
+                        DataType::String(data) => {
+                            key_type.push(KeyType::from_str(data.trim()).map_err(|_| { header_error(i, j, format!("convert string {} to enum KeyType error", data)) })?);
+                        }
+                        other => {
+                            return Err(header_error(i, j, format!("excel string expected, got: {}", other)));
+                        }
+                    }
+                }
+                3 => {
+                    match data_type {
+                        DataType::String(data) => match data.trim().strip_prefix(RULES_MARKER) {
+                            Some(rules) => {
+                                cell_rule.push(CellRule::parse_rules(rules).map_err(|e| { header_error(i, j, e.to_string()) })?);
+                            }
+                            None => {
+                                if CellRule::parse_rules(data).map(|rules| { !rules.is_empty() }).unwrap_or(false) {
+                                    warn!("{}", header_error(i, j, format!("note {:?} reads as column rules, write {}{} to check them", data, RULES_MARKER, data.trim())));
+                                }
+                                cell_rule.push(vec![]);
+                            }
+                        },
+                        DataType::Empty => {
+                            cell_rule.push(vec![]);
+                        }
+                        other => {
+                            return Err(header_error(i, j, format!("excel string expected, got: {}", other)));
+                        }
+                    }
+                }
+                4 => {}
+                _ => {
+                    row_data.push(data_type.to_string().trim().to_string());
+                }
+            }
+        }
+        if i >= 5 {
+            excel_data.push(row_data);
+            row_number.push(start_row + i + 1);
+        }
+    }
+    let column_number = (0..cell_name.len()).map(|j| { start_column + j + 1 }).collect();
+    let config = GameConfig::builder()
+        .name(sheet_name.to_string())
+        .cell_name(cell_name)
+        .data(excel_data)
+        .cell_type(cell_type)
+        .cell_ref(cell_ref)
+        .cell_rule(cell_rule)
+        .key_type(key_type)
+        .source(display_path.to_string())
+        .row_number(row_number)
+        .column_number(column_number)
+        .build();
+    return if !config.key_type.is_empty() {
+        Ok(Some(config))
+    } else {
+        Ok(None)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::path::Path;
+
+    use crate::excel::excel_define::{CellRule, CellType, KeyType};
+    use crate::excel::report::ErrorReport;
+    use crate::excel::workbook::{InputFormat, read_workbook};
+
+    #[test]
+    fn test_read_csv() -> anyhow::Result<()> {
+        assert_eq!(InputFormat::detect(Path::new("item.XLS")), Some(InputFormat::Xls));
+        assert_eq!(InputFormat::detect(Path::new("item.ods")), Some(InputFormat::Ods));
+        assert_eq!(InputFormat::detect(Path::new("item.txt")), None);
+        let path = env::temp_dir().join("test_read_csv_item.csv");
+        std::fs::write(&path, "id,name,pos\nuint,string,vector2_int\nallkey,all,server\nrules:unique,a note,\ncomment,,\n1001,\"a,b\",\"1,2\"\n1002,,\n")?;
+        let mut report = ErrorReport::default();
+        let configs = read_workbook(path, &mut report)?;
+        assert!(report.is_empty());
+        assert_eq!(configs.len(), 1);
+        let config = &configs[0];
+        assert_eq!(config.name, "test_read_csv_item");
+        assert_eq!(config.cell_type, vec![CellType::UInt, CellType::String, CellType::Vector2Int]);
+        assert_eq!(config.key_type, vec![KeyType::AllKey, KeyType::All, KeyType::Server]);
+        assert_eq!(config.data, vec![vec!["1001", "a,b", "1,2"], vec!["1002", "", ""]]);
+        assert_eq!(config.row_number, vec![6, 7]);
+        assert_eq!(config.cell_rule, vec![vec![CellRule::Unique], vec![], vec![]]);
+
+        let path = env::temp_dir().join("test_read_csv_shop.csv");
+        std::fs::write(&path, "id\nuint\nallkey\n\n\n1\n\"2\nb\"\n3\n")?;
+        let configs = read_workbook(path, &mut report)?;
+        assert!(report.is_empty());
+        assert_eq!(configs[0].data, vec![vec!["1"], vec!["2\nb"], vec!["3"]]);
+        Ok(())
+    }
+}
@@ -5,8 +5,36 @@ use mlua::prelude::LuaUserData;
 
 use stardust_derive::{lua_function, lua_helper};
 
+use crate::excel::report::{ErrorReport, ExcelError};
+
 pub struct RustUtil;
 
+/// a fresh lua state with `RustUtil` registered as global and an empty report for `RustUtil.ReportError`
+pub fn new_lua_state() -> anyhow::Result<mlua::Lua> {
+    let lua = mlua::Lua::new();
+    lua.set_app_data(ErrorReport::default());
+    let rust_util = lua.create_proxy::<RustUtil>()?;
+    lua.globals().set("RustUtil", rust_util)?;
+    Ok(lua)
+}
+
+/// errors reported by lua config classes since the last call
+pub fn take_lua_errors(lua: &mlua::Lua) -> ErrorReport {
+    lua.app_data_mut::<ErrorReport>().map(|mut report| { std::mem::take(&mut *report) }).unwrap_or_default()
+}
+
+/// call `ReloadGameConfigs` defined in `init_config.lua` with the generated lua dir, its default dir if `None`,
+/// the lua side keeps the previous configs on failure
+pub fn reload_lua_configs(lua: &mlua::Lua, dir: Option<&str>) -> anyhow::Result<()> {
+    let reload: mlua::Function = lua.globals().get("ReloadGameConfigs")?;
+    let (ok, err): (bool, Option<String>) = reload.call(dir)?;
+    take_lua_errors(lua).log();
+    if !ok {
+        return Err(anyhow::anyhow!(format!("lua config reload failed: {}", err.unwrap_or_default())));
+    }
+    Ok(())
+}
+
 #[lua_helper]
 impl RustUtil {
     #[lua_function]
@@ -39,6 +67,27 @@ impl RustUtil {
         Ok(files)
     }
 
+    /// `row` is the index into the config data, the excel row is derived assuming the sheet starts at `A1`
+    #[lua_function]
+    fn report_error(lua: &mlua::Lua, sheet: String, row: Option<usize>, message: String) -> mlua::Result<()> {
+        let error = ExcelError {
+            row: row.map(|row| { row + 5 }),
+            ..ExcelError::sheet("", &sheet, message)
+        };
+        match lua.app_data_mut::<ErrorReport>() {
+            None => Err(anyhow::anyhow!("lua state has no error report, create it with new_lua_state").to_lua_err()),
+            Some(mut report) => {
+                report.push(error.into());
+                Ok(())
+            }
+        }
+    }
+
+    #[lua_function]
+    fn error_count(lua: &mlua::Lua) -> mlua::Result<usize> {
+        Ok(lua.app_data_ref::<ErrorReport>().map(|report| { report.len() }).unwrap_or_default())
+    }
+
     #[lua_function]
     fn strip_suffix(string: String, p: String) -> mlua::Result<Option<String>> {
         Ok(string.strip_suffix(&p).map(|t| { t.to_string() }))